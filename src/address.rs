@@ -0,0 +1,493 @@
+#![allow(unused)]
+
+/* Decoding and checking of base58check (legacy) and bech32/bech32m (segwit) addresses */
+
+use crate::script::{ScriptBuilder, OP_0, OP_1, OP_16, OP_CHECKSIG, OP_DUP, OP_EQUAL, OP_EQUALVERIFY, OP_HASH160};
+use crate::hashes::sha256d;
+use std::fmt;
+
+const BASE58_ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+const BECH32_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const BECH32_CONST: u32 = 1;
+const BECH32M_CONST: u32 = 0x2bc830a3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Network {
+    Mainnet,
+    Testnet,
+    Regtest,
+}
+
+impl Network {
+    fn p2pkh_prefix(self) -> u8 {
+        match self {
+            Network::Mainnet => 0x00,
+            Network::Testnet | Network::Regtest => 0x6f,
+        }
+    }
+
+    fn p2sh_prefix(self) -> u8 {
+        match self {
+            Network::Mainnet => 0x05,
+            Network::Testnet | Network::Regtest => 0xc4,
+        }
+    }
+
+    pub fn bech32_hrp(self) -> &'static str {
+        match self {
+            Network::Mainnet => "bc",
+            Network::Testnet => "tb",
+            Network::Regtest => "bcrt",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AddressType {
+    P2pkh,
+    P2sh,
+    P2wpkh,
+    P2wsh,
+    P2tr,
+    // A valid future witness version/program nobody has defined rules for yet
+    WitnessUnknown,
+}
+
+impl AddressType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AddressType::P2pkh => "p2pkh",
+            AddressType::P2sh => "p2sh",
+            AddressType::P2wpkh => "p2wpkh",
+            AddressType::P2wsh => "p2wsh",
+            AddressType::P2tr => "p2tr",
+            AddressType::WitnessUnknown => "witness_unknown",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AddressInfo {
+    // Legacy testnet and regtest addresses look the same, those decode as Testnet
+    pub network: Network,
+    pub address_type: AddressType,
+    pub witness_version: Option<u8>,
+    // The hash (legacy) or witness program (segwit) the address commits to
+    pub payload: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AddressError {
+    Empty,
+    InvalidBase58Character { character: char, position: usize },
+    InvalidBech32Character { character: char, position: usize },
+    MixedCase,
+    MissingSeparator,
+    InvalidChecksum,
+    UnknownPrefix(u8),
+    UnknownHrp(String),
+    InvalidLength(usize),
+    InvalidWitnessVersion(u8),
+    // bech32 used for v1+ or bech32m for v0 (BIP350)
+    WrongChecksumVariant,
+    InvalidPadding,
+    WrongNetwork { expected: Network, found: Network },
+}
+
+impl fmt::Display for AddressError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AddressError::Empty => write!(f, "empty address"),
+            AddressError::InvalidBase58Character { character, position } => {
+                write!(f, "invalid base58 character '{}' at position {}", character, position)
+            }
+            AddressError::InvalidBech32Character { character, position } => {
+                write!(f, "invalid bech32 character '{}' at position {}", character, position)
+            }
+            AddressError::MixedCase => write!(f, "bech32 address mixes upper and lower case"),
+            AddressError::MissingSeparator => write!(f, "bech32 address has no '1' separator"),
+            AddressError::InvalidChecksum => write!(f, "invalid checksum"),
+            AddressError::UnknownPrefix(prefix) => write!(f, "unknown address version byte 0x{:02x}", prefix),
+            AddressError::UnknownHrp(hrp) => write!(f, "unknown human readable part '{}'", hrp),
+            AddressError::InvalidLength(len) => write!(f, "invalid payload length {}", len),
+            AddressError::InvalidWitnessVersion(version) => write!(f, "invalid witness version {}", version),
+            AddressError::WrongChecksumVariant => write!(f, "wrong checksum variant for witness version"),
+            AddressError::InvalidPadding => write!(f, "invalid padding in data part"),
+            AddressError::WrongNetwork { expected, found } => {
+                write!(f, "address is for {:?}, expected {:?}", found, expected)
+            }
+        }
+    }
+}
+
+fn base58_decode(s: &str) -> Result<Vec<u8>, AddressError> {
+    let mut bytes: Vec<u8> = Vec::new(); // big endian
+    for (position, character) in s.chars().enumerate() {
+        let digit = BASE58_ALPHABET
+            .iter()
+            .position(|c| *c as char == character)
+            .ok_or(AddressError::InvalidBase58Character { character, position })?;
+        let mut carry = digit as u32;
+        for byte in bytes.iter_mut().rev() {
+            carry += *byte as u32 * 58;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.insert(0, carry as u8);
+            carry >>= 8;
+        }
+    }
+    // every leading '1' is a leading zero byte
+    let zeros = s.chars().take_while(|c| *c == '1').count();
+    let mut result = vec![0; zeros];
+    result.extend(bytes);
+    Ok(result)
+}
+
+fn base58_encode(data: &[u8]) -> String {
+    let mut digits: Vec<u8> = Vec::new(); // base58 digits, little endian
+    for byte in data {
+        let mut carry = *byte as u32;
+        for digit in digits.iter_mut() {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+    let zeros = data.iter().take_while(|b| **b == 0).count();
+    let mut result = "1".repeat(zeros);
+    result.extend(digits.iter().rev().map(|d| BASE58_ALPHABET[*d as usize] as char));
+    result
+}
+
+fn base58check_encode(payload: &[u8]) -> String {
+    let checksum = sha256d(payload).0;
+    base58_encode(&[payload, &checksum[..4]].concat())
+}
+
+fn base58check_decode(s: &str) -> Result<Vec<u8>, AddressError> {
+    let data = base58_decode(s)?;
+    if data.len() < 4 {
+        return Err(AddressError::InvalidLength(data.len()));
+    }
+    let (payload, checksum) = data.split_at(data.len() - 4);
+    if sha256d(payload).0[..4] != *checksum {
+        return Err(AddressError::InvalidChecksum);
+    }
+    Ok(payload.to_vec())
+}
+
+fn bech32_polymod(values: &[u8]) -> u32 {
+    const GENERATOR: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+    let mut chk: u32 = 1;
+    for value in values {
+        let top = chk >> 25;
+        chk = ((chk & 0x1ffffff) << 5) ^ *value as u32;
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                chk ^= generator;
+            }
+        }
+    }
+    chk
+}
+
+fn hrp_expand(hrp: &str) -> Vec<u8> {
+    let mut values: Vec<u8> = hrp.bytes().map(|b| b >> 5).collect();
+    values.push(0);
+    values.extend(hrp.bytes().map(|b| b & 31));
+    values
+}
+
+// Returns the hrp, the 5 bit data without checksum, and the checksum constant that matched
+fn bech32_decode(s: &str) -> Result<(String, Vec<u8>, u32), AddressError> {
+    if s.chars().any(|c| c.is_ascii_lowercase()) && s.chars().any(|c| c.is_ascii_uppercase()) {
+        return Err(AddressError::MixedCase);
+    }
+    let s = s.to_ascii_lowercase();
+    let separator = s.rfind('1').ok_or(AddressError::MissingSeparator)?;
+    let (hrp, data) = (&s[..separator], &s[separator + 1..]);
+    if hrp.is_empty() || data.len() < 6 {
+        return Err(AddressError::InvalidLength(data.len()));
+    }
+    let mut values = Vec::with_capacity(data.len());
+    for (i, character) in data.chars().enumerate() {
+        let value = BECH32_CHARSET
+            .iter()
+            .position(|c| *c as char == character)
+            .ok_or(AddressError::InvalidBech32Character { character, position: separator + 1 + i })?;
+        values.push(value as u8);
+    }
+    let constant = bech32_polymod(&[hrp_expand(hrp), values.clone()].concat());
+    if constant != BECH32_CONST && constant != BECH32M_CONST {
+        return Err(AddressError::InvalidChecksum);
+    }
+    values.truncate(values.len() - 6);
+    Ok((hrp.to_string(), values, constant))
+}
+
+fn bech32_encode(hrp: &str, data: &[u8], constant: u32) -> String {
+    let values = [hrp_expand(hrp), data.to_vec(), vec![0; 6]].concat();
+    let polymod = bech32_polymod(&values) ^ constant;
+    let checksum = (0..6).map(|i| ((polymod >> (5 * (5 - i))) & 31) as u8);
+    let mut result = format!("{}1", hrp);
+    result.extend(data.iter().copied().chain(checksum).map(|v| BECH32_CHARSET[v as usize] as char));
+    result
+}
+
+fn convert_bits(data: &[u8], from: u32, to: u32, pad: bool) -> Result<Vec<u8>, AddressError> {
+    let mut acc: u32 = 0;
+    let mut bits: u32 = 0;
+    let mut result = Vec::new();
+    let max = (1 << to) - 1;
+    for value in data {
+        acc = (acc << from) | *value as u32;
+        bits += from;
+        while bits >= to {
+            bits -= to;
+            result.push(((acc >> bits) & max) as u8);
+        }
+    }
+    if pad {
+        if bits > 0 {
+            result.push(((acc << (to - bits)) & max) as u8);
+        }
+    } else if bits >= from || (acc << (to - bits)) & max != 0 {
+        return Err(AddressError::InvalidPadding);
+    }
+    Ok(result)
+}
+
+fn decode_legacy(address: &str) -> Result<AddressInfo, AddressError> {
+    let payload = base58check_decode(address)?;
+    if payload.len() != 21 {
+        return Err(AddressError::InvalidLength(payload.len()));
+    }
+    let (network, address_type) = match payload[0] {
+        0x00 => (Network::Mainnet, AddressType::P2pkh),
+        0x05 => (Network::Mainnet, AddressType::P2sh),
+        0x6f => (Network::Testnet, AddressType::P2pkh),
+        0xc4 => (Network::Testnet, AddressType::P2sh),
+        prefix => return Err(AddressError::UnknownPrefix(prefix)),
+    };
+    Ok(AddressInfo {
+        network,
+        address_type,
+        witness_version: None,
+        payload: payload[1..].to_vec(),
+    })
+}
+
+fn decode_segwit(address: &str) -> Result<AddressInfo, AddressError> {
+    let (hrp, data, constant) = bech32_decode(address)?;
+    let network = match hrp.as_str() {
+        "bc" => Network::Mainnet,
+        "tb" => Network::Testnet,
+        "bcrt" => Network::Regtest,
+        _ => return Err(AddressError::UnknownHrp(hrp)),
+    };
+    let (&version, program) = data.split_first().ok_or(AddressError::InvalidLength(0))?;
+    if version > 16 {
+        return Err(AddressError::InvalidWitnessVersion(version));
+    }
+    // BIP350: version 0 uses bech32, everything newer bech32m
+    let expected = if version == 0 { BECH32_CONST } else { BECH32M_CONST };
+    if constant != expected {
+        return Err(AddressError::WrongChecksumVariant);
+    }
+    let program = convert_bits(program, 5, 8, false)?;
+    let address_type = match (version, program.len()) {
+        (_, len) if !(2..=40).contains(&len) => return Err(AddressError::InvalidLength(len)),
+        (0, 20) => AddressType::P2wpkh,
+        (0, 32) => AddressType::P2wsh,
+        (0, len) => return Err(AddressError::InvalidLength(len)),
+        (1, 32) => AddressType::P2tr,
+        _ => AddressType::WitnessUnknown,
+    };
+    Ok(AddressInfo {
+        network,
+        address_type,
+        witness_version: Some(version),
+        payload: program,
+    })
+}
+
+// validateaddress: decode any supported address and report what it is
+pub fn validate_address(address: &str) -> Result<AddressInfo, AddressError> {
+    if address.is_empty() {
+        return Err(AddressError::Empty);
+    }
+    let lower = address.to_ascii_lowercase();
+    let looks_segwit = ["bc1", "tb1", "bcrt1"].iter().any(|hrp| lower.starts_with(hrp));
+    if looks_segwit {
+        decode_segwit(address)
+    } else {
+        decode_legacy(address)
+    }
+}
+
+// Same as validate_address but the address must belong to `network`
+pub fn validate_address_for(address: &str, network: Network) -> Result<AddressInfo, AddressError> {
+    let info = validate_address(address)?;
+    // legacy testnet and regtest addresses can't be told apart
+    let matches = info.network == network
+        || (info.witness_version.is_none() && info.network == Network::Testnet && network == Network::Regtest);
+    if !matches {
+        return Err(AddressError::WrongNetwork { expected: network, found: info.network });
+    }
+    Ok(info)
+}
+
+// The scriptPubKey paying to an address
+pub fn address_to_script(address: &str) -> Result<Vec<u8>, AddressError> {
+    let info = validate_address(address)?;
+    let payload = &info.payload;
+    let script = match info.witness_version {
+        None if info.address_type == AddressType::P2pkh => ScriptBuilder::new()
+            .push_opcode(OP_DUP)
+            .push_opcode(OP_HASH160)
+            .push_bytes(payload)
+            .push_opcode(OP_EQUALVERIFY)
+            .push_opcode(OP_CHECKSIG)
+            .into_bytes(),
+        None => ScriptBuilder::new().push_opcode(OP_HASH160).push_bytes(payload).push_opcode(OP_EQUAL).into_bytes(),
+        Some(version) => ScriptBuilder::witness_program(version, payload),
+    };
+    Ok(script)
+}
+
+// The address for a standard output script, None for scripts that have no address form
+pub fn script_to_address(script: &[u8], network: Network) -> Option<String> {
+    match script {
+        [OP_DUP, OP_HASH160, 20, hash @ .., OP_EQUALVERIFY, OP_CHECKSIG] if hash.len() == 20 => {
+            Some(base58check_encode(&[&[network.p2pkh_prefix()], hash].concat()))
+        }
+        [OP_HASH160, 20, hash @ .., OP_EQUAL] if hash.len() == 20 => {
+            Some(base58check_encode(&[&[network.p2sh_prefix()], hash].concat()))
+        }
+        [version_op, len, program @ ..] if program.len() == *len as usize && (2..=40).contains(&program.len()) => {
+            let version = match *version_op {
+                OP_0 => 0,
+                op @ OP_1..=OP_16 => op - OP_1 + 1,
+                _ => return None,
+            };
+            if version == 0 && program.len() != 20 && program.len() != 32 {
+                return None;
+            }
+            let mut data = vec![version];
+            data.extend(convert_bits(program, 8, 5, true).ok()?);
+            let constant = if version == 0 { BECH32_CONST } else { BECH32M_CONST };
+            Some(bech32_encode(network.bech32_hrp(), &data, constant))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_legacy_addresses() {
+        let info = validate_address("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa").unwrap();
+        assert_eq!(info.network, Network::Mainnet);
+        assert_eq!(info.address_type, AddressType::P2pkh);
+        assert_eq!(hex::encode(&info.payload), "62e907b15cbf27d5425399ebf6f0fb50ebb88f18");
+
+        let info = validate_address("3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy").unwrap();
+        assert_eq!(info.address_type, AddressType::P2sh);
+
+        let info = validate_address("mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn").unwrap();
+        assert_eq!(info.network, Network::Testnet);
+        assert_eq!(info.address_type, AddressType::P2pkh);
+    }
+
+    #[test]
+    fn test_segwit_addresses() {
+        let info = validate_address("BC1QW508D6QEJXTDG4Y5R3ZARVARY0C5XW7KV8F3T4").unwrap();
+        assert_eq!(info.address_type, AddressType::P2wpkh);
+        assert_eq!(hex::encode(&info.payload), "751e76e8199196d454941c45d1b3a323f1433bd6");
+
+        let info = validate_address("tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7").unwrap();
+        assert_eq!(info.network, Network::Testnet);
+        assert_eq!(info.address_type, AddressType::P2wsh);
+
+        let info = validate_address("bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0").unwrap();
+        assert_eq!(info.address_type, AddressType::P2tr);
+        assert_eq!(info.witness_version, Some(1));
+
+        let info = validate_address("bc1pw508d6qejxtdg4y5r3zarvary0c5xw7kw508d6qejxtdg4y5r3zarvary0c5xw7kt5nd6y").unwrap();
+        assert_eq!(info.address_type, AddressType::WitnessUnknown);
+        assert_eq!(info.payload.len(), 40);
+    }
+
+    #[test]
+    fn test_invalid_addresses() {
+        assert_eq!(
+            validate_address("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNb"),
+            Err(AddressError::InvalidChecksum)
+        );
+        assert_eq!(
+            validate_address("1A1zP1eP5QGefi2DMPTfTL5SLmv7Divf0a"),
+            Err(AddressError::InvalidBase58Character { character: '0', position: 32 })
+        );
+        assert_eq!(
+            validate_address("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t5"),
+            Err(AddressError::InvalidChecksum)
+        );
+        assert_eq!(
+            validate_address("bc1QW508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4"),
+            Err(AddressError::MixedCase)
+        );
+        // version 1 program with a bech32 (not bech32m) checksum, from BIP350
+        assert_eq!(
+            validate_address("bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqh2y7hd"),
+            Err(AddressError::WrongChecksumVariant)
+        );
+        assert_eq!(validate_address(""), Err(AddressError::Empty));
+    }
+
+    #[test]
+    fn test_address_to_script() {
+        let script = address_to_script("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa").unwrap();
+        assert_eq!(hex::encode(&script), "76a91462e907b15cbf27d5425399ebf6f0fb50ebb88f1888ac");
+        let script = address_to_script("BC1QW508D6QEJXTDG4Y5R3ZARVARY0C5XW7KV8F3T4").unwrap();
+        assert_eq!(hex::encode(&script), "0014751e76e8199196d454941c45d1b3a323f1433bd6");
+        let script = address_to_script("bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0").unwrap();
+        assert_eq!(hex::encode(&script), "512079be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798");
+        assert!(address_to_script("not an address").is_err());
+    }
+
+    #[test]
+    fn test_script_address_round_trip() {
+        let addresses = [
+            ("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa", Network::Mainnet),
+            ("3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy", Network::Mainnet),
+            ("mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn", Network::Testnet),
+            ("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4", Network::Mainnet),
+            ("tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7", Network::Testnet),
+            ("bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0", Network::Mainnet),
+            ("bc1pw508d6qejxtdg4y5r3zarvary0c5xw7kw508d6qejxtdg4y5r3zarvary0c5xw7kt5nd6y", Network::Mainnet),
+        ];
+        for (address, network) in addresses {
+            let script = address_to_script(address).unwrap();
+            assert_eq!(script_to_address(&script, network).as_deref(), Some(address));
+        }
+        // OP_RETURN has no address
+        assert_eq!(script_to_address(&[0x6a, 0x01, 0x00], Network::Mainnet), None);
+    }
+
+    #[test]
+    fn test_wrong_network() {
+        assert_eq!(
+            validate_address_for("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa", Network::Testnet),
+            Err(AddressError::WrongNetwork { expected: Network::Testnet, found: Network::Mainnet })
+        );
+        assert!(validate_address_for("mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn", Network::Regtest).is_ok());
+    }
+}
@@ -1,51 +1,420 @@
+#![allow(unused)]
+
 use std::collections::LinkedList as List;
+use std::cmp::Ordering;
 use std::collections::HashMap;
-use hex;
-use sha2::{Digest, Sha256};
+use std::fmt;
+use std::io;
+use std::ops::{Bound, RangeBounds};
+use crate::codec::{self, CompactSize, Decodable, DecodeError, Encodable, Reader};
+use crate::hashes::sha256d;
+use crate::merkle::{merkle_root, txid_bytes};
+use crate::bloom::{Counter, FilterStats, TxidFilter};
+use crate::store::{ChainStore, MemoryStore};
+use crate::utxo::{Coin, UtxoSetHash, UtxoStats};
+use crate::validation::RulePipeline;
+use crate::timedata::TimeData;
+use crate::address::address_to_script;
+use crate::script::count_sigops;
+
+pub const SEQUENCE_FINAL: u32 = 0xFFFFFFFF;
+// Any input with a sequence at or below this opts the transaction into replacement (BIP125)
+pub const MAX_BIP125_RBF_SEQUENCE: u32 = 0xFFFFFFFD;
+
+// BIP68 relative lock times, only for transactions with version >= 2
+pub const SEQUENCE_LOCKTIME_DISABLE_FLAG: u32 = 1 << 31;
+pub const SEQUENCE_LOCKTIME_TYPE_FLAG: u32 = 1 << 22; // set: time based, unset: height based
+pub const SEQUENCE_LOCKTIME_MASK: u32 = 0x0000FFFF;
+pub const SEQUENCE_LOCKTIME_GRANULARITY: u32 = 9; // time locks count in units of 512 seconds
+pub const TX_VERSION_DEFAULT: i32 = 1;
+
+pub const COIN: u64 = 100_000_000;
+// No single amount, and no sum of amounts, may go above the 21 million coin supply
+pub const MAX_MONEY: u64 = 21_000_000 * COIN;
+
+// Consensus limit on signature operations per block, in cost units (legacy sigops count 4 each)
+pub const MAX_BLOCK_SIGOPS_COST: usize = 80_000;
+pub const WITNESS_SCALE_FACTOR: usize = 4;
+
+pub fn money_range(value: u64) -> bool {
+    value <= MAX_MONEY
+}
+
+// Metadata keys in the store
+const META_UNDO_PREFIX: &str = "undo-";
 
 #[derive(Clone)]
-pub struct BlockChain {
-    blocks: List<Block>,
+pub struct BlockChain<S = MemoryStore> {
+    blocks: List<Block>, // the active chain, also written to the store
     height: u128,
-    utxo_set: HashMap<String, TxOut>, // Unspent Transaction Outputs (UTXO)
+    store: S, // blocks, the UTXO set (keyed by "txid:vout") and metadata
+    utxo_stats: UtxoStats,
+    utxo_hash: UtxoSetHash,
+    undo: List<BlockUndo>, // coins spent by each block, so it can be disconnected again
+    tx_index: Option<HashMap<String, TxLocation>>, // txid -> location, None with the txindex turned off
+    spent_by: HashMap<String, (String, usize)>, // "txid:vout" -> (spending txid, input index)
+    stats: ChainStats,
+    time_data: TimeData,
+    txid_filter: TxidFilter, // all confirmed txids, to skip scanning blocks for unknown ones
+    filter_lookups: Counter,
+    filter_misses: Counter,
+    rules: RulePipeline<S>,
 }
 
 impl BlockChain {
     pub fn new() -> Self {
+        BlockChain::with_store(MemoryStore::new())
+    }
+
+    // Size the txid filter for the expected number of transactions
+    pub fn with_txid_filter(capacity: usize, fp_rate: f64) -> Self {
+        let mut chain = BlockChain::new();
+        chain.txid_filter = TxidFilter::new(capacity, fp_rate);
+        chain
+    }
+}
+
+impl<S: ChainStore> BlockChain<S> {
+    // An empty chain on top of the store, whatever the store already holds is ignored. See load().
+    pub fn with_store(store: S) -> Self {
         BlockChain {
             blocks: List::new(),
             height: 0,
-            utxo_set: HashMap::new(),
+            store,
+            utxo_stats: UtxoStats::default(),
+            utxo_hash: UtxoSetHash::default(),
+            undo: List::new(),
+            tx_index: Some(HashMap::new()),
+            spent_by: HashMap::new(),
+            stats: ChainStats::default(),
+            time_data: TimeData::new(),
+            txid_filter: TxidFilter::default(),
+            filter_lookups: Counter::default(),
+            filter_misses: Counter::default(),
+            rules: RulePipeline::default(),
         }
     }
 
+    // Pick up the chain a store was left with: walk back from the stored tip, then
+    // rebuild the in-memory indexes. The store keeps the tip and UTXO set in step,
+    // so the coins are used as stored.
+    pub fn load(store: S) -> Self {
+        let mut chain = BlockChain::with_store(store);
+        let mut blocks = Vec::new();
+        let mut next = chain.store.get_tip();
+        while let Some(hash) = next.take().filter(|hash| !hash.is_empty()) {
+            let Some(block) = chain.store.get_block(&hash) else { break };
+            if block.height > 0 {
+                next = Some(block.prev_hash.clone());
+            }
+            blocks.push(block);
+        }
+        for block in blocks.into_iter().rev() {
+            index_spends(&mut chain.spent_by, &block);
+            let undo = chain
+                .store
+                .get_meta(&format!("{}{}", META_UNDO_PREFIX, block.hash))
+                .and_then(|data| codec::deserialize(&data).ok())
+                .unwrap_or_default();
+            chain.undo.push_back(undo);
+            chain.blocks.push_back(block);
+            chain.height += 1;
+        }
+        for (key, coin) in chain.store.coins() {
+            chain.utxo_stats.add(coin);
+            chain.utxo_hash.insert(key, coin);
+        }
+        chain.reindex();
+        let tx_count = chain.blocks.iter().map(Block::tx_count).sum();
+        chain.rebuild_txid_filter(chain.txid_filter.capacity().max(tx_count));
+        chain
+    }
+
+    // Without the txindex lookups by txid fall back to scanning the active chain
+    pub fn set_txindex(&mut self, enabled: bool) {
+        match (enabled, self.tx_index.is_some()) {
+            (true, false) => self.reindex(),
+            (false, true) => self.tx_index = None,
+            _ => {}
+        }
+    }
+
+    pub fn has_txindex(&self) -> bool {
+        self.tx_index.is_some()
+    }
+
+    // Build the txindex from scratch out of the active chain, turning it on if it was off
+    pub fn reindex(&mut self) {
+        let mut index = HashMap::new();
+        for (height, block) in self.blocks.iter().enumerate() {
+            index_transactions(&mut index, block, height);
+        }
+        self.tx_index = Some(index);
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.store.flush()
+    }
+
+    fn write_tip(&mut self) {
+        let tip = self.blocks.back().map(|block| block.hash.clone()).unwrap_or_default();
+        self.store.put_tip(&tip);
+    }
+
+    fn index_tip_txids(&mut self) {
+        let Some(tip) = self.blocks.back() else { return };
+        let needed = self.txid_filter.stats().items + tip.transactions.len();
+        if needed > self.txid_filter.capacity() {
+            // rebuild at least twice as big so the false positive rate stays on target
+            self.rebuild_txid_filter(needed.max(self.txid_filter.capacity() * 2));
+        } else {
+            for tx in &tip.transactions {
+                self.txid_filter.insert(&tx.txid);
+            }
+        }
+    }
+
+    fn rebuild_txid_filter(&mut self, capacity: usize) {
+        let mut filter = TxidFilter::new(capacity, self.txid_filter.fp_rate());
+        for block in &self.blocks {
+            for tx in &block.transactions {
+                filter.insert(&tx.txid);
+            }
+        }
+        self.txid_filter = filter;
+    }
+
+    pub fn get_txid_filter_stats(&self) -> FilterStats {
+        self.txid_filter.stats()
+    }
+
+    // (lookups, lookups answered by the filter alone)
+    pub fn get_txid_filter_hits(&self) -> (u64, u64) {
+        (self.filter_lookups.get(), self.filter_misses.get())
+    }
+
     pub fn add_block(&mut self, block: Block) {
         if self.is_valid_block(&block) {
+            self.connect_block(block);
+        } else {
+            self.stats.blocks_rejected += 1;
+        }
+    }
+
+    // No checks, callers validate first
+    pub(crate) fn connect_block(&mut self, block: Block) {
+        let mut undo = BlockUndo::default();
+        let height = self.blocks.len();
+        for tx in &block.transactions {
+            let mut removed = Vec::new();
+            for txin in &tx.inputs {
+                let key = outpoint_key(&txin.prev_txid, txin.out as usize);
+                if let Some(spent) = self.spend_coin(&key) {
+                    removed.push((key, spent));
+                }
+            }
+            for (idx, txout) in tx.outputs.iter().enumerate() {
+                let key = outpoint_key(&tx.calculate_txid(), idx);
+                // a duplicate txid overwrites the unspent outputs of the first one
+                if let Some(replaced) = self.add_coin(key.clone(), Coin::new(txout.clone(), height)) {
+                    removed.push((key, replaced));
+                }
+            }
+            removed.retain(|(_, coin)| coin.height != height);
+            undo.txs.push(removed);
+        }
+        if let Some(index) = &mut self.tx_index {
+            index_transactions(index, &block, self.blocks.len());
+        }
+        index_spends(&mut self.spent_by, &block);
+        self.store.put_block(&block);
+        self.store.put_meta(&format!("{}{}", META_UNDO_PREFIX, block.hash), &codec::serialize(&undo));
+        self.blocks.push_back(block);
+        self.undo.push_back(undo);
+        self.height += 1;
+        self.write_tip();
+        self.index_tip_txids();
+        self.stats.blocks_connected += 1;
+    }
+
+    // Remove the tip block and put back the coins it spent
+    pub fn disconnect_tip(&mut self) -> Option<Block> {
+        let block = self.blocks.pop_back()?;
+        let mut undo = self.undo.pop_back().unwrap_or_default();
+        // newest transaction first, so each one finds the set as it left it
+        for tx in block.transactions.iter().rev() {
+            if let Some(index) = &mut self.tx_index {
+                if index.get(&tx.txid).is_some_and(|location| location.block_hash == block.hash) {
+                    index.remove(&tx.txid);
+                }
+            }
+            for idx in 0..tx.outputs.len() {
+                self.spend_coin(&outpoint_key(&tx.calculate_txid(), idx));
+            }
+            for txin in &tx.inputs {
+                let key = outpoint_key(&txin.prev_txid, txin.out as usize);
+                if self.spent_by.get(&key).is_some_and(|(spender, _)| *spender == tx.txid) {
+                    self.spent_by.remove(&key);
+                }
+            }
+            for (key, coin) in undo.txs.pop().unwrap_or_default().into_iter().rev() {
+                self.add_coin(key, coin);
+            }
+        }
+        // a txid this block repeated is still in an earlier block, point the index back at it
+        if let Some(index) = &mut self.tx_index {
             for tx in &block.transactions {
-                for txin in &tx.inputs {
-                    self.utxo_set.remove(&txin.prev_txid);
+                if index.contains_key(&tx.txid) {
+                    continue;
                 }
-                for (idx, txout) in tx.outputs.iter().enumerate() {
-                    self.utxo_set.insert(tx.calculate_txid(), txout.clone());
+                let earlier = self.blocks.iter().enumerate().rev().find_map(|(height, earlier)| {
+                    let position = earlier.transactions.iter().position(|other| other.txid == tx.txid)?;
+                    Some(TxLocation {
+                        block_hash: earlier.hash.clone(),
+                        height,
+                        position,
+                        offset: earlier.tx_offsets()[position],
+                    })
+                });
+                if let Some(location) = earlier {
+                    index.insert(tx.txid.clone(), location);
                 }
             }
-            self.blocks.push_back(block);
-            self.height += 1;
         }
+        self.height -= 1;
+        self.write_tip();
+        self.stats.blocks_disconnected += 1;
+        Some(block)
     }
 
-    pub fn is_valid_block(&self, block: &Block) -> bool {
-        if block.height > 0 {
-            self.get_block_by_hash(&block.prev_hash).is_some()
-        } else {
-            true // Genesis block
+    // Cheap: only remembers where the tip was
+    pub fn snapshot(&self) -> ChainSnapshot {
+        ChainSnapshot {
+            block_count: self.blocks.len(),
+            tip_hash: self.get_best_block_hash().map(String::from),
         }
     }
 
+    // Rewind to a snapshot taken earlier. Fails (and changes nothing) if the
+    // snapshot's tip is no longer part of the chain, e.g. after rolling back past it.
+    pub fn restore(&mut self, snapshot: &ChainSnapshot) -> bool {
+        if snapshot.block_count > self.blocks.len() {
+            return false;
+        }
+        let tip_hash = snapshot
+            .block_count
+            .checked_sub(1)
+            .and_then(|height| self.get_block_by_height(height))
+            .map(|block| block.hash.clone());
+        if tip_hash != snapshot.tip_hash {
+            return false;
+        }
+        self.disconnect_to(snapshot.block_count);
+        true
+    }
+
+    // Disconnect blocks until the block at `height` is the tip. Returns the
+    // disconnected blocks, tip first.
+    pub fn rollback_to_height(&mut self, height: usize) -> Vec<Block> {
+        self.disconnect_to(height + 1)
+    }
+
+    fn disconnect_to(&mut self, block_count: usize) -> Vec<Block> {
+        let mut disconnected = Vec::new();
+        while self.blocks.len() > block_count {
+            disconnected.extend(self.disconnect_tip());
+        }
+        if !disconnected.is_empty() {
+            // bloom filters can't forget items
+            self.rebuild_txid_filter(self.txid_filter.capacity());
+            self.stats.rollbacks += 1;
+            self.stats.max_rollback_depth = self.stats.max_rollback_depth.max(disconnected.len());
+        }
+        disconnected
+    }
+
+    pub fn get_chain_stats(&self) -> &ChainStats {
+        &self.stats
+    }
+
+    pub fn get_utxo(&self, txid: &str, vout: usize) -> Option<&TxOut> {
+        self.store.get_coin(&outpoint_key(txid, vout)).map(|coin| &coin.txout)
+    }
+
+    pub fn get_utxo_count(&self) -> usize {
+        self.store.coin_count()
+    }
+
+    pub fn get_coin(&self, txid: &str, vout: usize) -> Option<&Coin> {
+        self.store.get_coin(&outpoint_key(txid, vout))
+    }
+
+    // Every unspent output as (txid, vout, coin), in no particular order
+    pub fn utxos(&self) -> impl Iterator<Item = (&str, usize, &Coin)> {
+        self.store.coins().filter_map(|(key, coin)| {
+            let (txid, vout) = key.rsplit_once(':')?;
+            Some((txid, vout.parse().ok()?, coin))
+        })
+    }
+
+    pub fn utxo_stats(&self) -> UtxoStats {
+        self.utxo_stats.with_ages(self.blocks.len())
+    }
+
+    pub fn utxo_set_hash(&self) -> &UtxoSetHash {
+        &self.utxo_hash
+    }
+
+    // All UTXO set changes go through these two so the stats and hash stay in sync
+    // Returns the coin that was already at `key`, if any
+    fn add_coin(&mut self, key: String, coin: Coin) -> Option<Coin> {
+        self.utxo_stats.add(&coin);
+        self.utxo_hash.insert(&key, &coin);
+        let replaced = self.store.put_coin(key.clone(), coin)?;
+        self.utxo_stats.remove(&replaced);
+        self.utxo_hash.remove(&key, &replaced);
+        Some(replaced)
+    }
+
+    fn spend_coin(&mut self, key: &str) -> Option<Coin> {
+        let coin = self.store.remove_coin(key)?;
+        self.utxo_stats.remove(&coin);
+        self.utxo_hash.remove(key, &coin);
+        Some(coin)
+    }
+
+    pub fn is_valid_block(&self, block: &Block) -> bool {
+        self.check_block(block).is_ok()
+    }
+
+    // Like is_valid_block but says why, using bitcoin core's reject reasons
+    pub fn check_block(&self, block: &Block) -> Result<(), &'static str> {
+        self.rules.check(self, block)
+    }
+
+    // Only the rules that don't depend on which branch the block is on
+    pub fn check_block_context_free(&self, block: &Block) -> Result<(), &'static str> {
+        self.rules.check_context_free(self, block)
+    }
+
+    // The rules check_block runs, to switch individual rules off or swap them out
+    pub fn rules_mut(&mut self) -> &mut RulePipeline<S> {
+        &mut self.rules
+    }
+
     pub fn get_block_by_hash(&self, hash: &str) -> Option<&Block> {
         self.blocks.iter().find(|b| b.hash == hash)
     }
 
+    // Position of the block in the active chain
+    pub fn get_block_height(&self, hash: &str) -> Option<usize> {
+        self.blocks.iter().position(|b| b.hash == hash)
+    }
+
     pub fn get_block_by_height(&self, height: usize) -> Option<&Block> {
         self.blocks.iter().nth(height)
     }
@@ -54,25 +423,216 @@ impl BlockChain {
         self.blocks.len()
     }
 
+    // Blocks with heights in the range, in height order. Walks from whichever
+    // end of the chain is closer.
+    pub fn blocks_in_range(&self, range: impl RangeBounds<usize>) -> Vec<&Block> {
+        let len = self.blocks.len();
+        let start = match range.start_bound() {
+            Bound::Included(&s) => s,
+            Bound::Excluded(&s) => s.saturating_add(1),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&e) => e.saturating_add(1),
+            Bound::Excluded(&e) => e,
+            Bound::Unbounded => len,
+        }
+        .min(len);
+        if start >= end {
+            return Vec::new();
+        }
+
+        if start <= len - end {
+            self.blocks.iter().skip(start).take(end - start).collect()
+        } else {
+            let mut blocks: Vec<&Block> = self.blocks.iter().rev().skip(len - end).take(end - start).collect();
+            blocks.reverse();
+            blocks
+        }
+    }
+
+    // The last n blocks, tip first
+    pub fn last_n_blocks(&self, n: usize) -> Vec<&Block> {
+        self.blocks.iter().rev().take(n).collect()
+    }
+
+    // Seconds between consecutive blocks over the last `window` intervals, None with
+    // fewer than two blocks. Timestamps aren't monotonic so intervals can be negative.
+    pub fn block_interval_stats(&self, window: usize) -> Option<IntervalStats> {
+        let blocks = self.last_n_blocks(window.saturating_add(1));
+        let mut intervals: Vec<i64> = blocks
+            .windows(2)
+            .map(|pair| pair[0].timestamp as i64 - pair[1].timestamp as i64)
+            .collect();
+        if intervals.is_empty() {
+            return None;
+        }
+        intervals.sort_unstable();
+        // nearest rank
+        let percentile = |p: usize| intervals[(intervals.len() * p).div_ceil(100).max(1) - 1];
+        Some(IntervalStats {
+            count: intervals.len(),
+            mean: intervals.iter().sum::<i64>() as f64 / intervals.len() as f64,
+            min: intervals[0],
+            median: percentile(50),
+            p90: percentile(90),
+            max: intervals[intervals.len() - 1],
+        })
+    }
+
     pub fn get_transaction(&self, txid: &str) -> Option<&Transaction> {
-        for block in &self.blocks {
-            if let Some(tx) = block.get_transaction(txid) {
-                return Some(tx);
-            }
+        self.filter_lookups.increment();
+        if !self.txid_filter.may_contain(txid) {
+            self.filter_misses.increment();
+            return None;
+        }
+        let (_, height, index) = self.get_tx_location(txid)?;
+        self.get_block_by_height(height)?.transactions.iter().nth(index)
+    }
+
+    // (block hash, block height, position in block)
+    pub fn get_tx_location(&self, txid: &str) -> Option<(&str, usize, usize)> {
+        match &self.tx_index {
+            Some(index) => index
+                .get(txid)
+                .map(|location| (location.block_hash.as_str(), location.height, location.position)),
+            None if !self.txid_filter.may_contain(txid) => None,
+            None => self.blocks.iter().enumerate().find_map(|(height, block)| {
+                let position = block.transactions.iter().position(|tx| tx.txid == txid)?;
+                Some((block.hash.as_str(), height, position))
+            }),
         }
-        None
+    }
+
+    // The confirmed transaction spending an output, as (txid, input index)
+    pub fn get_spender(&self, txid: &str, vout: usize) -> Option<(&str, usize)> {
+        self.spent_by
+            .get(&outpoint_key(txid, vout))
+            .map(|(spender, input)| (spender.as_str(), *input))
+    }
+
+    // Only answered with the txindex on
+    pub fn get_tx_index_entry(&self, txid: &str) -> Option<&TxLocation> {
+        self.tx_index.as_ref()?.get(txid)
+    }
+
+    // 1 for a transaction in the tip block, None if it isn't in the chain
+    pub fn get_confirmations(&self, txid: &str) -> Option<usize> {
+        let (_, height, _) = self.get_tx_location(txid)?;
+        Some(self.blocks.len() - height)
     }
 
     pub fn get_best_block_hash(&self) -> Option<&str> {
         self.blocks.back().map(|block| block.hash.as_str())
     }
+
+    // Peers report their clock in the version message, feed the offset here
+    pub fn add_time_sample(&mut self, peer: &str, offset: i64) {
+        self.time_data.add_sample(peer, offset);
+    }
+
+    pub fn get_adjusted_time(&self) -> u64 {
+        self.time_data.adjusted_time()
+    }
+
+    pub fn get_time_offset(&self) -> i64 {
+        self.time_data.offset()
+    }
+
+    pub fn get_warnings(&self) -> Option<&str> {
+        self.time_data.warning()
+    }
 }
-#[derive(Clone)]
+
+// Where a confirmed transaction is. offset is the byte position of the
+// transaction inside the serialized block, so it can be read without decoding the rest.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TxLocation {
+    pub block_hash: String,
+    pub height: usize,
+    pub position: usize,
+    pub offset: usize,
+}
+
+fn index_transactions(index: &mut HashMap<String, TxLocation>, block: &Block, height: usize) {
+    for (position, (tx, offset)) in block.transactions.iter().zip(block.tx_offsets()).enumerate() {
+        let location = TxLocation {
+            block_hash: block.hash.clone(),
+            height,
+            position,
+            offset,
+        };
+        index.insert(tx.txid.clone(), location);
+    }
+}
+
+fn index_spends(spent_by: &mut HashMap<String, (String, usize)>, block: &Block) {
+    for tx in &block.transactions {
+        for (input, txin) in tx.inputs.iter().enumerate() {
+            spent_by.insert(outpoint_key(&txin.prev_txid, txin.out as usize), (tx.txid.clone(), input));
+        }
+    }
+}
+
+pub fn outpoint_key(txid: &str, vout: usize) -> String {
+    format!("{}:{}", txid, vout)
+}
+
+// One entry per transaction: the coins it took out of the set, spent by an input or
+// overwritten by an output. Coins created earlier in the same block aren't recorded,
+// disconnecting the block removes them anyway.
+#[derive(Clone, Default, Debug, PartialEq)]
+struct BlockUndo {
+    txs: Vec<Vec<(String, Coin)>>,
+}
+
+impl Encodable for BlockUndo {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.txs.encode(buf);
+    }
+}
+
+impl Decodable for BlockUndo {
+    fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(BlockUndo { txs: Vec::decode(reader)? })
+    }
+}
+
+// Block intervals in seconds, see BlockChain::block_interval_stats
+#[derive(Clone, Debug, PartialEq)]
+pub struct IntervalStats {
+    pub count: usize,
+    pub mean: f64,
+    pub min: i64,
+    pub median: i64,
+    pub p90: i64,
+    pub max: i64,
+}
+
+// Running totals since the chain was created
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct ChainStats {
+    pub blocks_connected: u64,
+    pub blocks_rejected: u64,
+    pub blocks_disconnected: u64,
+    pub rollbacks: u64,
+    pub max_rollback_depth: usize,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct ChainSnapshot {
+    block_count: usize,
+    tip_hash: Option<String>,
+}
+
+// Ordered by height then hash, the remaining fields only break ties between blocks with stale hashes
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Block {
     pub hash: String,
     pub height: u64,
     pub transactions: List<Transaction>,
     pub prev_hash: String,
+    pub timestamp: u64,
     pub nonce: u64,
 }
 
@@ -83,16 +643,27 @@ impl Block {
             height: 0,
             transactions: List::new(),
             prev_hash,
+            timestamp: 0,
             nonce: 0,
         }
     }
 
+    // sha256d of the header fields, the transactions are covered through the merkle root
     pub fn calculate_hash(&self) -> String {
-        let mut hasher = Sha256::new();
-        hasher.update(self.height.to_string());
-        hasher.update(&self.prev_hash);
-        hasher.update(self.nonce.to_string());
-        hex::encode(hasher.finalize())
+        let mut header = Vec::new();
+        self.encode_header(&mut header);
+        self.merkle_root_bytes().encode(&mut header);
+        sha256d(&header).to_hex()
+    }
+
+    // Commits to every transaction so the hash changes if any of them does
+    pub fn merkle_root(&self) -> String {
+        hex::encode(self.merkle_root_bytes())
+    }
+
+    fn merkle_root_bytes(&self) -> [u8; 32] {
+        let txids: Vec<[u8; 32]> = self.transactions.iter().map(|tx| txid_bytes(&tx.txid)).collect();
+        merkle_root(&txids)
     }
 
     pub fn add_transaction(&mut self, transaction: Transaction) {
@@ -103,61 +674,298 @@ impl Block {
     pub fn get_transaction(&self, txid: &str) -> Option<&Transaction> {
         self.transactions.iter().find(|tx| tx.txid == txid)
     }
+
+    pub fn tx_count(&self) -> usize {
+        self.transactions.len()
+    }
+
+    pub fn txid_at(&self, index: usize) -> Option<&str> {
+        self.transactions.iter().nth(index).map(|tx| tx.txid.as_str())
+    }
+
+    // Up to `limit` transactions starting at `offset`, for paging through big blocks
+    pub fn transactions_page(&self, offset: usize, limit: usize) -> Vec<&Transaction> {
+        self.transactions.iter().skip(offset).take(limit).collect()
+    }
+
+    // Byte offset of each transaction in serialize()
+    pub fn tx_offsets(&self) -> Vec<usize> {
+        let mut prefix = Vec::new();
+        self.encode_header(&mut prefix);
+        CompactSize(self.transactions.len() as u64).encode(&mut prefix);
+        let mut offset = prefix.len();
+        self.transactions
+            .iter()
+            .map(|tx| {
+                let start = offset;
+                offset += tx.serialize().len();
+                start
+            })
+            .collect()
+    }
+
+    fn encode_header(&self, buf: &mut Vec<u8>) {
+        self.prev_hash.encode(buf);
+        self.height.encode(buf);
+        self.timestamp.encode(buf);
+        self.nonce.encode(buf);
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        codec::serialize(self)
+    }
+
+    pub fn deserialize(data: &[u8]) -> Result<Block, DecodeError> {
+        codec::deserialize(data)
+    }
 }
 
+impl Ord for Block {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.height, &self.hash, &self.prev_hash, self.timestamp, self.nonce, &self.transactions).cmp(&(
+            other.height,
+            &other.hash,
+            &other.prev_hash,
+            other.timestamp,
+            other.nonce,
+            &other.transactions,
+        ))
+    }
+}
 
-#[derive(Clone)]
+impl PartialOrd for Block {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Block {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "block {} at height {} ({} txs)", self.hash, self.height, self.transactions.len())
+    }
+}
+
+impl Encodable for Block {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.encode_header(buf);
+        self.transactions.encode(buf);
+    }
+}
+
+// The hash isn't serialized, it's recomputed from the contents
+impl Decodable for Block {
+    fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let mut block = Block::new(String::decode(reader)?);
+        block.height = u64::decode(reader)?;
+        block.timestamp = u64::decode(reader)?;
+        block.nonce = u64::decode(reader)?;
+        block.transactions = List::decode(reader)?;
+        block.hash = block.calculate_hash();
+        Ok(block)
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RelativeLockTime {
+    Blocks(u16),
+    Seconds(u32),
+}
+
+// Ordered by txid, the remaining fields only break ties between transactions with stale txids
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Transaction {
+    pub version: i32,
     pub inputs: List<TxIn>,
     pub outputs: List<TxOut>,
+    pub lock_time: u32,
     pub txid: String,
 }
 
 impl Transaction {
     pub fn new(inputs: List<TxIn>, outputs: List<TxOut>) -> Self {
+        Transaction::with_version(TX_VERSION_DEFAULT, inputs, outputs)
+    }
+
+    pub fn with_version(version: i32, inputs: List<TxIn>, outputs: List<TxOut>) -> Self {
+        Transaction::with_lock_time(version, inputs, outputs, 0)
+    }
+
+    // nLockTime is carried and hashed but not enforced yet
+    pub fn with_lock_time(version: i32, inputs: List<TxIn>, outputs: List<TxOut>, lock_time: u32) -> Self {
         let mut tx = Transaction {
+            version,
             txid: String::new(),
             inputs,
             outputs,
+            lock_time,
         };
         tx.txid = tx.calculate_txid();
         tx
     }
 
+    // sha256d of the serialized transaction
     pub fn calculate_txid(&self) -> String {
-        let mut hasher = Sha256::new();
-        for input in self.inputs.iter() {
-            hasher.update(&input.prev_txid);
-            hasher.update(input.out.to_string());
-            hasher.update(&input.signature);
-        }
-        for output in self.outputs.iter() {
-            hasher.update(&output.public_address);
-            hasher.update(output.satoshis.to_string());
+        sha256d(&self.serialize()).to_hex()
+    }
+
+    // Total of the outputs, rejecting any output or running total above MAX_MONEY
+    pub fn check_output_values(&self) -> Result<u64, &'static str> {
+        let mut total: u64 = 0;
+        for output in &self.outputs {
+            if !money_range(output.satoshis) {
+                return Err("bad-txns-vout-toolarge");
+            }
+            total = total
+                .checked_add(output.satoshis)
+                .filter(|total| money_range(*total))
+                .ok_or("bad-txns-txouttotal-toolarge")?;
         }
-        hex::encode(hasher.finalize())
+        Ok(total)
+    }
+
+    // Legacy sigops in the output scripts. Inputs carry no scripts here and P2SH
+    // redeem scripts aren't evaluated, so outputs are all there is to count.
+    pub fn sigop_count(&self) -> usize {
+        self.outputs
+            .iter()
+            .filter_map(|output| address_to_script(&output.public_address).ok())
+            .map(|script| count_sigops(&script, false))
+            .sum()
+    }
+
+    pub fn is_rbf_signaling(&self) -> bool {
+        self.inputs.iter().any(|input| input.sequence <= MAX_BIP125_RBF_SEQUENCE)
+    }
+
+    // Sequence numbers only carry BIP68 meaning from version 2 on
+    pub fn relative_lock_times(&self) -> Vec<Option<RelativeLockTime>> {
+        self.inputs
+            .iter()
+            .map(|input| if self.version >= 2 { input.relative_lock_time() } else { None })
+            .collect()
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        codec::serialize(self)
+    }
+
+    pub fn deserialize(data: &[u8]) -> Result<Transaction, DecodeError> {
+        codec::deserialize(data)
     }
 }
 
-#[derive(Clone)]
+impl Ord for Transaction {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (&self.txid, self.version, &self.inputs, &self.outputs, self.lock_time).cmp(&(
+            &other.txid,
+            other.version,
+            &other.inputs,
+            &other.outputs,
+            other.lock_time,
+        ))
+    }
+}
+
+impl PartialOrd for Transaction {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Transaction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "tx {} ({} in, {} out)", self.txid, self.inputs.len(), self.outputs.len())
+    }
+}
+
+impl Encodable for Transaction {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.version.encode(buf);
+        self.inputs.encode(buf);
+        self.outputs.encode(buf);
+        self.lock_time.encode(buf);
+    }
+}
+
+impl Decodable for Transaction {
+    fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let version = i32::decode(reader)?;
+        let inputs = List::decode(reader)?;
+        let outputs = List::decode(reader)?;
+        Ok(Transaction::with_lock_time(version, inputs, outputs, u32::decode(reader)?))
+    }
+}
+
+// Ordered by the outpoint it spends
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct TxIn {
     pub prev_txid: String,
-    pub out: usize,
+    pub out: u32, // vout on the wire, so every value encodes as itself
     pub signature: String, // to spend the output
+    pub sequence: u32,
 }
 
 impl TxIn {
-    pub fn new(prev_txid: String, out: usize, signature: String) -> Self {
+    pub fn new(prev_txid: String, out: u32, signature: String) -> Self {
+        TxIn::with_sequence(prev_txid, out, signature, SEQUENCE_FINAL)
+    }
+
+    pub fn with_sequence(prev_txid: String, out: u32, signature: String, sequence: u32) -> Self {
         TxIn {
             prev_txid,
             out,
             signature,
+            sequence,
         }
     }
+
+    // What the sequence number would mean in a version 2 transaction
+    pub fn relative_lock_time(&self) -> Option<RelativeLockTime> {
+        if self.sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0 {
+            return None;
+        }
+        let value = self.sequence & SEQUENCE_LOCKTIME_MASK;
+        if self.sequence & SEQUENCE_LOCKTIME_TYPE_FLAG != 0 {
+            Some(RelativeLockTime::Seconds(value << SEQUENCE_LOCKTIME_GRANULARITY))
+        } else {
+            Some(RelativeLockTime::Blocks(value as u16))
+        }
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        codec::serialize(self)
+    }
+}
+
+// The outpoint, same format as outpoint_key
+impl fmt::Display for TxIn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.prev_txid, self.out)
+    }
 }
 
+impl Encodable for TxIn {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.prev_txid.encode(buf);
+        self.out.encode(buf);
+        self.signature.encode(buf);
+        self.sequence.encode(buf);
+    }
+}
 
-#[derive(Clone)]
+impl Decodable for TxIn {
+    fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let prev_txid = String::decode(reader)?;
+        let out = u32::decode(reader)?;
+        let signature = String::decode(reader)?;
+        Ok(TxIn::with_sequence(prev_txid, out, signature, u32::decode(reader)?))
+    }
+}
+
+
+// Ordered by address then value
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct TxOut {
     pub public_address: String,
     pub satoshis: u64,
@@ -170,12 +978,37 @@ impl TxOut {
             satoshis,
         }
     }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        codec::serialize(self)
+    }
+}
+
+impl fmt::Display for TxOut {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} sat to {}", self.satoshis, self.public_address)
+    }
+}
+
+impl Encodable for TxOut {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.satoshis.encode(buf);
+        self.public_address.encode(buf);
+    }
+}
+
+impl Decodable for TxOut {
+    fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let satoshis = u64::decode(reader)?;
+        Ok(TxOut::new(String::decode(reader)?, satoshis))
+    }
 }
 
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::timedata::MAX_FUTURE_BLOCK_TIME;
 
     #[test]
     fn test_txin() {
@@ -183,6 +1016,7 @@ mod tests {
         assert_eq!(txin.prev_txid, "prev_output");
         assert_eq!(txin.out, 0);
         assert_eq!(txin.signature, "signature");
+        assert_eq!(txin.sequence, SEQUENCE_FINAL);
     }
 
     #[test]
@@ -200,6 +1034,99 @@ mod tests {
         assert!(!tx.txid.is_empty());
     }
 
+    #[test]
+    fn test_transaction_rbf_signaling() {
+        let txout = TxOut::new(String::from("public_address"), 100);
+        let final_in = TxIn::new(String::from("prev_output"), 0, String::from("signature"));
+        let tx = Transaction::new(vec![final_in.clone()].into_iter().collect(), vec![txout.clone()].into_iter().collect());
+        assert!(!tx.is_rbf_signaling());
+
+        let rbf_in = TxIn::with_sequence(String::from("prev_output"), 1, String::from("signature"), MAX_BIP125_RBF_SEQUENCE);
+        let tx = Transaction::new(vec![final_in, rbf_in].into_iter().collect(), vec![txout].into_iter().collect());
+        assert!(tx.is_rbf_signaling());
+    }
+
+    #[test]
+    fn test_transaction_version() {
+        let txin = TxIn::with_sequence(String::from("prev_output"), 0, String::from("signature"), 10);
+        let txout = TxOut::new(String::from("public_address"), 100);
+        let v1 = Transaction::new(vec![txin.clone()].into_iter().collect(), vec![txout.clone()].into_iter().collect());
+        let v2 = Transaction::with_version(2, vec![txin].into_iter().collect(), vec![txout].into_iter().collect());
+        assert_eq!(v1.version, TX_VERSION_DEFAULT);
+        assert_ne!(v1.txid, v2.txid);
+        assert_eq!(v1.relative_lock_times(), vec![None]);
+        assert_eq!(v2.relative_lock_times(), vec![Some(RelativeLockTime::Blocks(10))]);
+    }
+
+    #[test]
+    fn test_txin_relative_lock_time() {
+        let txin = |sequence| TxIn::with_sequence(String::from("prev_output"), 0, String::from("signature"), sequence);
+        assert_eq!(txin(SEQUENCE_FINAL).relative_lock_time(), None);
+        assert_eq!(txin(5).relative_lock_time(), Some(RelativeLockTime::Blocks(5)));
+        assert_eq!(txin(SEQUENCE_LOCKTIME_TYPE_FLAG | 2).relative_lock_time(), Some(RelativeLockTime::Seconds(1024)));
+    }
+
+    #[test]
+    fn test_serialize_round_trip() {
+        let mut block = Block::new(String::from("prev_hash"));
+        block.height = 3;
+        block.timestamp = 1_700_000_000;
+        let txin = TxIn::with_sequence(String::from("prev_output"), 1, String::from("signature"), 7);
+        let txout = TxOut::new(String::from("public_address"), 100);
+        block.add_transaction(Transaction::with_version(2, vec![txin].into_iter().collect(), vec![txout].into_iter().collect()));
+
+        assert_eq!(Block::deserialize(&block.serialize()).unwrap(), block);
+
+        let bytes = block.serialize();
+        assert_eq!(Block::deserialize(&bytes[..bytes.len() - 1]).err(), Some(DecodeError::UnexpectedEof));
+        assert_eq!(Block::deserialize(&[bytes.clone(), vec![0]].concat()).err(), Some(DecodeError::TrailingBytes(1)));
+    }
+
+    #[test]
+    fn test_vout_range() {
+        let spend = |vout| {
+            let txin = TxIn::new(String::from("prev_output"), vout, String::from("signature"));
+            Transaction::new(List::from([txin]), List::from([TxOut::new(String::from("a"), 10)]))
+        };
+        let top = spend(u32::MAX);
+        assert_eq!(Transaction::deserialize(&top.serialize()).unwrap(), top);
+        assert_ne!(top.txid, spend(0).txid);
+
+        // an output index past u32::MAX can't be spent, so it doesn't alias vout 0
+        let mut blockchain = BlockChain::new();
+        let mut genesis = Block::new(String::new());
+        genesis.add_transaction(Transaction::new(List::new(), List::from([TxOut::new(String::from("a"), 50)])));
+        let coinbase = genesis.transactions.front().unwrap().txid.clone();
+        blockchain.add_block(genesis);
+        assert!(blockchain.get_coin(&coinbase, 0).is_some());
+        assert!(blockchain.get_coin(&coinbase, u32::MAX as usize + 1).is_none());
+    }
+
+    #[test]
+    fn test_canonical_ordering() {
+        let mut blocks = Vec::new();
+        for height in [2, 0, 1] {
+            let mut block = Block::new(String::from("prev_hash"));
+            block.height = height;
+            block.add_transaction(Transaction::new(List::new(), List::from([TxOut::new(String::from("a"), height)])));
+            blocks.push(block);
+        }
+        blocks.sort();
+        assert_eq!(blocks.iter().map(|block| block.height).collect::<Vec<_>>(), vec![0, 1, 2]);
+
+        let txs: std::collections::BTreeSet<Transaction> = blocks.iter().flat_map(|block| block.transactions.iter().cloned()).collect();
+        let txids: Vec<&str> = txs.iter().map(|tx| tx.txid.as_str()).collect();
+        assert!(txids.windows(2).all(|pair| pair[0] < pair[1]));
+
+        let txin = TxIn::new(String::from("prev"), 1, String::new());
+        assert_eq!(txin.to_string(), "prev:1");
+        assert_eq!(TxOut::new(String::from("a"), 5).to_string(), "5 sat to a");
+        assert_eq!(blocks[0].to_string(), format!("block {} at height 0 (1 txs)", blocks[0].hash));
+        let mut set = std::collections::HashSet::new();
+        assert!(set.insert(txin.clone()));
+        assert!(!set.insert(txin));
+    }
+
     #[test]
     fn test_block() {
         let mut block = Block::new(String::from("prev_hash"));
@@ -218,6 +1145,24 @@ mod tests {
         assert_eq!(block.transactions.len(), 1);
     }
 
+    #[test]
+    fn test_block_transactions_page() {
+        let mut block = Block::new(String::from("prev_hash"));
+        for i in 0..5 {
+            let txout = TxOut::new(String::from("public_address"), i);
+            block.add_transaction(Transaction::new(List::new(), vec![txout].into_iter().collect()));
+        }
+        assert_eq!(block.tx_count(), 5);
+
+        let first = block.transactions_page(0, 2);
+        let last = block.transactions_page(4, 2);
+        assert_eq!(first.len(), 2);
+        assert_eq!(last.len(), 1);
+        assert!(block.transactions_page(5, 2).is_empty());
+        assert_eq!(block.txid_at(4), Some(last[0].txid.as_str()));
+        assert_eq!(block.txid_at(5), None);
+    }
+
     #[test]
     fn test_blockchain() {
         let blockchain = BlockChain::new();
@@ -233,4 +1178,368 @@ mod tests {
         blockchain.add_block(block2);
         assert_eq!(blockchain.get_block_count(), 2);
     }
+
+    #[test]
+    fn test_blockchain_txid_filter() {
+        let mut blockchain = BlockChain::with_txid_filter(2, 0.01);
+        let mut block = Block::new(String::new());
+        for i in 0..5 {
+            let txin = TxIn::new(format!("prev_output{}", i), 0, String::from("signature"));
+            let txout = TxOut::new(String::from("public_address"), 100);
+            block.add_transaction(Transaction::new(vec![txin].into_iter().collect(), vec![txout].into_iter().collect()));
+        }
+        let txids: Vec<String> = block.transactions.iter().map(|tx| tx.txid.clone()).collect();
+        blockchain.add_block(block);
+
+        for txid in &txids {
+            assert!(blockchain.get_transaction(txid).is_some());
+        }
+        assert!(blockchain.get_transaction("unknown").is_none());
+        // the filter grew past its initial capacity
+        assert!(blockchain.get_txid_filter_stats().capacity >= 5);
+        assert_eq!(blockchain.get_txid_filter_hits().0, 6);
+    }
+
+    fn spend_block(prev: &Block, spends: &[(&str, u32)], outputs: &[u64]) -> Block {
+        let mut block = Block::new(prev.hash.clone());
+        block.height = prev.height + 1;
+        let inputs: List<TxIn> = spends.iter().map(|(txid, vout)| TxIn::new(txid.to_string(), *vout, String::from("signature"))).collect();
+        let outputs: List<TxOut> = outputs.iter().map(|sats| TxOut::new(String::from("public_address"), *sats)).collect();
+        block.add_transaction(Transaction::new(inputs, outputs));
+        block
+    }
+
+    #[test]
+    fn test_output_values_capped() {
+        let mut blockchain = BlockChain::new();
+        let mut genesis = Block::new(String::new());
+        genesis.add_transaction(Transaction::new(List::new(), List::from([TxOut::new(String::from("a"), MAX_MONEY + 1)])));
+        assert_eq!(blockchain.check_block(&genesis), Err("bad-txns-vout-toolarge"));
+
+        // each output is in range but together they wrap a u64
+        let mut genesis = Block::new(String::new());
+        let outputs = List::from([TxOut::new(String::from("a"), MAX_MONEY), TxOut::new(String::from("b"), MAX_MONEY)]);
+        genesis.add_transaction(Transaction::new(List::new(), outputs));
+        assert_eq!(blockchain.check_block(&genesis), Err("bad-txns-txouttotal-toolarge"));
+        blockchain.add_block(genesis);
+        assert_eq!(blockchain.get_chain_stats().blocks_rejected, 1);
+    }
+
+    #[test]
+    fn test_block_sigop_limit() {
+        let blockchain = BlockChain::new();
+        let p2pkh = String::from("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2");
+        let limit = MAX_BLOCK_SIGOPS_COST / WITNESS_SCALE_FACTOR;
+        let outputs: List<TxOut> = (0..limit).map(|_| TxOut::new(p2pkh.clone(), 1)).collect();
+        let tx = Transaction::new(List::new(), outputs);
+        assert_eq!(tx.sigop_count(), limit);
+
+        let mut genesis = Block::new(String::new());
+        genesis.add_transaction(tx);
+        assert_eq!(blockchain.check_block(&genesis), Ok(()));
+        genesis.add_transaction(Transaction::new(List::new(), List::from([TxOut::new(p2pkh, 1)])));
+        assert_eq!(blockchain.check_block(&genesis), Err("bad-blk-sigops"));
+    }
+
+    #[test]
+    fn test_fees_checked() {
+        let mut blockchain = BlockChain::new();
+        let mut genesis = Block::new(String::new());
+        genesis.add_transaction(Transaction::new(List::new(), List::from([TxOut::new(String::from("a"), 50)])));
+        let coinbase = genesis.transactions.front().unwrap().txid.clone();
+        blockchain.add_block(genesis.clone());
+
+        let overspend = spend_block(&genesis, &[(&coinbase, 0)], &[30, 30]);
+        assert_eq!(blockchain.check_block(&overspend), Err("bad-txns-in-belowout"));
+        let spend = spend_block(&genesis, &[(&coinbase, 0)], &[30, 15]);
+        assert_eq!(blockchain.check_block(&spend), Ok(()));
+        // unknown coins can't be valued so only the outputs are checked
+        let unknown = spend_block(&genesis, &[("missing", 0)], &[MAX_MONEY]);
+        assert_eq!(blockchain.check_block(&unknown), Ok(()));
+    }
+
+    #[test]
+    fn test_blockchain_utxo_per_output() {
+        let mut blockchain = BlockChain::new();
+        let mut genesis = Block::new(String::new());
+        let outputs = vec![TxOut::new(String::from("a"), 50), TxOut::new(String::from("b"), 25)];
+        genesis.add_transaction(Transaction::new(List::new(), outputs.into_iter().collect()));
+        let txid = genesis.transactions.front().unwrap().txid.clone();
+        blockchain.add_block(genesis);
+        assert_eq!(blockchain.get_utxo(&txid, 0).unwrap().satoshis, 50);
+        assert_eq!(blockchain.get_utxo(&txid, 1).unwrap().satoshis, 25);
+    }
+
+    #[test]
+    fn test_blockchain_utxo_stats() {
+        let mut blockchain = BlockChain::new();
+        let mut genesis = Block::new(String::new());
+        let outputs = vec![
+            TxOut::new(String::from("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa"), 5_000),
+            TxOut::new(String::from("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2"), 100),
+        ];
+        genesis.add_transaction(Transaction::new(List::new(), outputs.into_iter().collect()));
+        let txid = genesis.transactions.front().unwrap().txid.clone();
+        blockchain.add_block(genesis.clone());
+
+        let stats = blockchain.utxo_stats();
+        assert_eq!(stats.count, 2);
+        assert_eq!(stats.total_value, 5_100);
+        assert_eq!(stats.dust_count, 1);
+        assert_eq!(stats.by_type["p2pkh"], (2, 5_100));
+
+        // spending the dust in the next block
+        blockchain.add_block(spend_block(&genesis, &[(&txid, 1)], &[50]));
+        let stats = blockchain.utxo_stats();
+        assert_eq!(stats.count, 2);
+        assert_eq!(stats.dust_count, 1); // the new 50 sat output is dust too
+        assert_eq!(stats.total_value, 5_050);
+        assert_eq!(blockchain.get_coin(&txid, 0).unwrap().height, 0);
+    }
+
+    #[test]
+    fn test_blockchain_snapshot_restore() {
+        let mut blockchain = BlockChain::new();
+        let mut genesis = Block::new(String::new());
+        genesis.add_transaction(Transaction::new(List::new(), vec![TxOut::new(String::from("miner"), 50)].into_iter().collect()));
+        let coinbase = genesis.transactions.front().unwrap().txid.clone();
+        blockchain.add_block(genesis.clone());
+        let snapshot = blockchain.snapshot();
+
+        let block1 = spend_block(&genesis, &[(&coinbase, 0)], &[30, 20]);
+        let block2 = spend_block(&block1, &[], &[10]);
+        blockchain.add_block(block1);
+        blockchain.add_block(block2);
+        assert_eq!(blockchain.get_block_count(), 3);
+        assert!(blockchain.get_utxo(&coinbase, 0).is_none());
+
+        assert!(blockchain.restore(&snapshot));
+        assert_eq!(blockchain.get_block_count(), 1);
+        assert_eq!(blockchain.get_best_block_hash(), Some(genesis.hash.as_str()));
+        assert_eq!(blockchain.get_utxo(&coinbase, 0).unwrap().satoshis, 50);
+        assert_eq!(blockchain.get_utxo_count(), 1);
+        assert_eq!(blockchain.utxo_stats().total_value, 50);
+    }
+
+    #[test]
+    fn test_disconnect_restores_utxo_set() {
+        use crate::builder::{BlockBuilder, TransactionBuilder};
+        let spend = |prev: &Transaction, satoshis| {
+            let txin = TxIn::new(prev.txid.clone(), 0, String::from("signature"));
+            TransactionBuilder::new().add_input(txin).add_output("a", satoshis).build().unwrap()
+        };
+        let coinbase = TransactionBuilder::new().add_output("miner", 50).build().unwrap();
+        let genesis = BlockBuilder::new().add_tx(coinbase.clone()).build().unwrap();
+        let mut blockchain = BlockChain::new();
+        blockchain.add_block(genesis.clone());
+        let after_genesis = *blockchain.utxo_set_hash();
+
+        // b spends an output created earlier in the same block
+        let a = spend(&coinbase, 40);
+        let b = spend(&a, 30);
+        let block1 = BlockBuilder::new().parent(&genesis).add_tx(a.clone()).add_tx(b).build().unwrap();
+        blockchain.add_block(block1);
+        assert_eq!(blockchain.get_utxo_count(), 1);
+        blockchain.disconnect_tip();
+        assert_eq!(blockchain.get_utxo_count(), 1);
+        assert!(blockchain.get_utxo(&a.txid, 0).is_none());
+        assert_eq!(blockchain.get_utxo(&coinbase.txid, 0).unwrap().satoshis, 50);
+        assert_eq!(*blockchain.utxo_set_hash(), after_genesis);
+
+        // the same coinbase again overwrites the unspent genesis output
+        let repeat = BlockBuilder::new().parent(&genesis).add_tx(coinbase.clone()).build().unwrap();
+        blockchain.add_block(repeat);
+        assert_eq!(blockchain.get_coin(&coinbase.txid, 0).unwrap().height, 1);
+        blockchain.disconnect_tip();
+        assert_eq!(blockchain.get_coin(&coinbase.txid, 0).unwrap().height, 0);
+        assert_eq!(*blockchain.utxo_set_hash(), after_genesis);
+    }
+
+    #[test]
+    fn test_blockchain_rollback_to_height() {
+        let mut blockchain = BlockChain::new();
+        let genesis = Block::new(String::new());
+        let block1 = spend_block(&genesis, &[], &[10]);
+        let block2 = spend_block(&block1, &[], &[20]);
+        let snapshot_at_2 = {
+            blockchain.add_block(genesis);
+            blockchain.add_block(block1.clone());
+            blockchain.add_block(block2.clone());
+            blockchain.snapshot()
+        };
+
+        let disconnected = blockchain.rollback_to_height(1);
+        assert_eq!(disconnected.len(), 1);
+        assert_eq!(disconnected[0].hash, block2.hash);
+        assert_eq!(blockchain.get_best_block_hash(), Some(block1.hash.as_str()));
+        let txid = block2.transactions.front().unwrap().txid.clone();
+        assert!(blockchain.get_transaction(&txid).is_none());
+
+        // the snapshot's tip is gone so it can't be restored
+        assert!(!blockchain.restore(&snapshot_at_2));
+        assert_eq!(blockchain.get_block_count(), 2);
+
+        let stats = blockchain.get_chain_stats();
+        assert_eq!(stats.blocks_connected, 3);
+        assert_eq!(stats.blocks_disconnected, 1);
+        assert_eq!(stats.rollbacks, 1);
+    }
+
+    #[test]
+    fn test_blockchain_tx_location() {
+        let mut blockchain = BlockChain::new();
+        let genesis = Block::new(String::new());
+        let block1 = spend_block(&genesis, &[], &[10]);
+        let txid = block1.transactions.front().unwrap().txid.clone();
+        blockchain.add_block(genesis.clone());
+        blockchain.add_block(block1.clone());
+
+        assert_eq!(blockchain.get_tx_location(&txid), Some((block1.hash.as_str(), 1, 0)));
+        assert_eq!(blockchain.get_confirmations(&txid), Some(1));
+        assert_eq!(blockchain.get_transaction(&txid).unwrap().txid, txid);
+
+        blockchain.add_block(spend_block(&block1, &[], &[20]));
+        assert_eq!(blockchain.get_confirmations(&txid), Some(2));
+
+        blockchain.rollback_to_height(0);
+        assert_eq!(blockchain.get_tx_location(&txid), None);
+        assert_eq!(blockchain.get_confirmations(&txid), None);
+    }
+
+    #[test]
+    fn test_blockchain_interval_stats() {
+        let mut blockchain = BlockChain::new();
+        assert_eq!(blockchain.block_interval_stats(10), None);
+        let mut prev = Block::new(String::new());
+        prev.add_transaction(Transaction::new(List::new(), List::from([TxOut::new(String::from("a"), 1)])));
+        blockchain.add_block(prev.clone());
+        // intervals 600, 300, 900, -60, 600
+        for timestamp in [600, 900, 1800, 1740, 2340] {
+            let mut block = spend_block(&prev, &[], &[timestamp]);
+            block.timestamp = timestamp;
+            block.hash = block.calculate_hash();
+            blockchain.add_block(block.clone());
+            prev = block;
+        }
+        let stats = blockchain.block_interval_stats(10).unwrap();
+        assert_eq!(stats.count, 5);
+        assert_eq!(stats.mean, 468.0);
+        assert_eq!((stats.min, stats.median, stats.p90, stats.max), (-60, 600, 900, 900));
+        // only the last two intervals
+        let recent = blockchain.block_interval_stats(2).unwrap();
+        assert_eq!((recent.count, recent.min, recent.max), (2, -60, 600));
+    }
+
+    #[test]
+    fn test_blockchain_optional_txindex() {
+        let mut blockchain = BlockChain::new();
+        let mut genesis = Block::new(String::new());
+        genesis.add_transaction(Transaction::new(List::new(), List::from([TxOut::new(String::from("a"), 5)])));
+        let mut block1 = spend_block(&genesis, &[], &[10]);
+        block1.add_transaction(Transaction::new(List::new(), List::from([TxOut::new(String::from("a"), 20)])));
+        blockchain.add_block(genesis);
+        blockchain.add_block(block1.clone());
+
+        // every offset points at that transaction's bytes in the serialized block
+        let raw = block1.serialize();
+        for tx in &block1.transactions {
+            let entry = blockchain.get_tx_index_entry(&tx.txid).unwrap();
+            let bytes = tx.serialize();
+            assert_eq!(&raw[entry.offset..entry.offset + bytes.len()], &bytes[..]);
+            assert_eq!(entry.block_hash, block1.hash);
+        }
+
+        let txid = block1.txid_at(1).unwrap();
+        blockchain.set_txindex(false);
+        assert!(!blockchain.has_txindex());
+        assert_eq!(blockchain.get_tx_index_entry(txid), None);
+        // still found by scanning, and kept up to date again once reindexed
+        assert_eq!(blockchain.get_tx_location(txid), Some((block1.hash.as_str(), 1, 1)));
+        blockchain.disconnect_tip();
+        blockchain.reindex();
+        assert_eq!(blockchain.get_tx_location(txid), None);
+        blockchain.add_block(block1.clone());
+        assert_eq!(blockchain.get_tx_index_entry(txid).map(|entry| entry.position), Some(1));
+    }
+
+    #[test]
+    fn test_txindex_duplicate_txid_disconnect() {
+        let coinbase = || Transaction::new(List::new(), List::from([TxOut::new(String::from("a"), 50)]));
+        let mut blockchain = BlockChain::new();
+        let mut genesis = Block::new(String::new());
+        genesis.add_transaction(coinbase());
+        let mut block1 = spend_block(&genesis, &[], &[10]);
+        block1.add_transaction(coinbase());
+        blockchain.add_block(genesis.clone());
+        blockchain.add_block(block1.clone());
+        assert!(blockchain.has_txindex());
+
+        let txid = coinbase().txid;
+        let position = block1.transactions.iter().position(|tx| tx.txid == txid).unwrap();
+        assert_eq!(blockchain.get_tx_location(&txid), Some((block1.hash.as_str(), 1, position)));
+        blockchain.disconnect_tip();
+        // the copy in genesis is still there and indexed again
+        assert_eq!(blockchain.get_tx_location(&txid), Some((genesis.hash.as_str(), 0, 0)));
+        assert_eq!(blockchain.get_tx_index_entry(&txid).map(|entry| entry.offset), Some(genesis.tx_offsets()[0]));
+        assert!(blockchain.get_transaction(&txid).is_some());
+    }
+
+    #[test]
+    fn test_blockchain_sequence_locks() {
+        let mut blockchain = BlockChain::new();
+        let mut genesis = Block::new(String::new());
+        genesis.add_transaction(Transaction::new(List::new(), vec![TxOut::new(String::from("miner"), 50)].into_iter().collect()));
+        let coinbase = genesis.transactions.front().unwrap().txid.clone();
+        blockchain.add_block(genesis.clone());
+
+        let locked_spend = |prev: &Block, version| {
+            let mut block = Block::new(prev.hash.clone());
+            block.height = prev.height + 1;
+            let txin = TxIn::with_sequence(coinbase.clone(), 0, String::from("signature"), 2);
+            let txout = TxOut::new(String::from("bob"), 50);
+            block.add_transaction(Transaction::with_version(version, vec![txin].into_iter().collect(), vec![txout].into_iter().collect()));
+            block
+        };
+
+        // only one confirmation so far, the lock needs two
+        assert!(!blockchain.is_valid_block(&locked_spend(&genesis, 2)));
+        // version 1 ignores the lock
+        assert!(blockchain.is_valid_block(&locked_spend(&genesis, 1)));
+
+        let block1 = spend_block(&genesis, &[], &[10]);
+        blockchain.add_block(block1.clone());
+        assert!(blockchain.is_valid_block(&locked_spend(&block1, 2)));
+    }
+
+    #[test]
+    fn test_blockchain_range_queries() {
+        let mut blockchain = BlockChain::new();
+        let mut prev = Block::new(String::new());
+        blockchain.add_block(prev.clone());
+        for _ in 0..9 {
+            let block = spend_block(&prev, &[], &[10]);
+            blockchain.add_block(block.clone());
+            prev = block;
+        }
+        let heights = |blocks: Vec<&Block>| blocks.iter().map(|b| b.height).collect::<Vec<_>>();
+
+        assert_eq!(heights(blockchain.blocks_in_range(2..5)), vec![2, 3, 4]);
+        assert_eq!(heights(blockchain.blocks_in_range(7..=9)), vec![7, 8, 9]);
+        assert_eq!(heights(blockchain.blocks_in_range(8..)), vec![8, 9]);
+        assert_eq!(heights(blockchain.blocks_in_range(..2)), vec![0, 1]);
+        assert_eq!(blockchain.blocks_in_range(5..20).len(), 5);
+        assert!(blockchain.blocks_in_range(20..30).is_empty());
+
+        assert_eq!(heights(blockchain.last_n_blocks(3)), vec![9, 8, 7]);
+        assert_eq!(blockchain.last_n_blocks(100).len(), 10);
+    }
+
+    #[test]
+    fn test_blockchain_rejects_future_block() {
+        let mut blockchain = BlockChain::new();
+        let mut block = Block::new(String::from("prev_hash"));
+        block.timestamp = blockchain.get_adjusted_time() + MAX_FUTURE_BLOCK_TIME + 60;
+        blockchain.add_block(block);
+        assert_eq!(blockchain.get_block_count(), 0);
+    }
 }
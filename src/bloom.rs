@@ -0,0 +1,152 @@
+#![allow(unused)]
+
+use crate::hashes::sha256;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+pub const DEFAULT_FILTER_ITEMS: usize = 1_000;
+pub const DEFAULT_FALSE_POSITIVE_RATE: f64 = 0.001;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FilterStats {
+    pub items: usize,
+    pub capacity: usize,
+    pub bits: usize,
+    pub hash_count: u32,
+    pub target_fp_rate: f64,
+    pub estimated_fp_rate: f64,
+}
+
+// Bloom filter over txids. "no" answers are always right, "maybe" answers
+// are wrong with roughly the configured false positive rate.
+#[derive(Clone)]
+pub struct TxidFilter {
+    bits: Vec<u64>,
+    bit_count: usize,
+    hash_count: u32,
+    items: usize,
+    capacity: usize,
+    fp_rate: f64,
+}
+
+impl TxidFilter {
+    pub fn new(capacity: usize, fp_rate: f64) -> Self {
+        let capacity = capacity.max(1);
+        let fp_rate = fp_rate.clamp(1e-9, 0.5);
+        let ln2 = std::f64::consts::LN_2;
+        // m = -n ln(p) / ln(2)^2 and k = m/n ln(2)
+        let bit_count = ((-(capacity as f64) * fp_rate.ln()) / (ln2 * ln2)).ceil().max(64.0) as usize;
+        let hash_count = ((bit_count as f64 / capacity as f64) * ln2).round().max(1.0) as u32;
+        TxidFilter {
+            bits: vec![0; bit_count.div_ceil(64)],
+            bit_count,
+            hash_count,
+            items: 0,
+            capacity,
+            fp_rate,
+        }
+    }
+
+    // Double hashing: bit i = h1 + i * h2
+    fn positions(&self, txid: &str) -> impl Iterator<Item = usize> + '_ {
+        let digest = sha256(txid.as_bytes()).0;
+        let h1 = u64::from_le_bytes(digest[0..8].try_into().unwrap());
+        let h2 = u64::from_le_bytes(digest[8..16].try_into().unwrap()) | 1;
+        (0..self.hash_count as u64).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % self.bit_count as u64) as usize)
+    }
+
+    pub fn insert(&mut self, txid: &str) {
+        let positions: Vec<usize> = self.positions(txid).collect();
+        for pos in positions {
+            self.bits[pos / 64] |= 1 << (pos % 64);
+        }
+        self.items += 1;
+    }
+
+    pub fn may_contain(&self, txid: &str) -> bool {
+        self.positions(txid).all(|pos| self.bits[pos / 64] & (1 << (pos % 64)) != 0)
+    }
+
+    // Past capacity the false positive rate climbs above the target
+    pub fn is_full(&self) -> bool {
+        self.items >= self.capacity
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn fp_rate(&self) -> f64 {
+        self.fp_rate
+    }
+
+    pub fn stats(&self) -> FilterStats {
+        // (1 - e^(-kn/m))^k
+        let k = self.hash_count as f64;
+        let exponent = -k * self.items as f64 / self.bit_count as f64;
+        FilterStats {
+            items: self.items,
+            capacity: self.capacity,
+            bits: self.bit_count,
+            hash_count: self.hash_count,
+            target_fp_rate: self.fp_rate,
+            estimated_fp_rate: (1.0 - exponent.exp()).powf(k),
+        }
+    }
+}
+
+// Counter that can be bumped through a shared reference, so lookups stay &self
+#[derive(Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub fn increment(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl Clone for Counter {
+    fn clone(&self) -> Self {
+        Counter(AtomicU64::new(self.get()))
+    }
+}
+
+impl Default for TxidFilter {
+    fn default() -> Self {
+        TxidFilter::new(DEFAULT_FILTER_ITEMS, DEFAULT_FALSE_POSITIVE_RATE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_false_negatives() {
+        let mut filter = TxidFilter::new(100, 0.01);
+        for i in 0..100 {
+            filter.insert(&format!("txid{}", i));
+        }
+        for i in 0..100 {
+            assert!(filter.may_contain(&format!("txid{}", i)));
+        }
+        assert!(filter.is_full());
+    }
+
+    #[test]
+    fn test_false_positive_rate() {
+        let mut filter = TxidFilter::new(1000, 0.01);
+        for i in 0..1000 {
+            filter.insert(&format!("txid{}", i));
+        }
+        let false_positives = (0..10_000).filter(|i| filter.may_contain(&format!("other{}", i))).count();
+        // allow some slack over the 1% target
+        assert!(false_positives < 300);
+        let stats = filter.stats();
+        assert_eq!(stats.items, 1000);
+        assert!(stats.estimated_fp_rate < 0.02);
+    }
+}
@@ -0,0 +1,206 @@
+#![allow(unused)]
+
+/* Fluent construction of blocks and transactions. build() checks the result
+   hangs together and hashes it once, instead of callers poking at public
+   fields and remembering to recompute the hash afterwards. */
+
+use crate::block::{Block, Transaction, TxIn, TxOut, TX_VERSION_DEFAULT};
+use std::collections::HashSet;
+use std::collections::LinkedList as List;
+
+pub struct TransactionBuilder {
+    version: i32,
+    inputs: List<TxIn>,
+    outputs: List<TxOut>,
+    lock_time: u32,
+}
+
+impl Default for TransactionBuilder {
+    fn default() -> Self {
+        TransactionBuilder {
+            version: TX_VERSION_DEFAULT,
+            inputs: List::new(),
+            outputs: List::new(),
+            lock_time: 0,
+        }
+    }
+}
+
+impl TransactionBuilder {
+    pub fn new() -> Self {
+        TransactionBuilder::default()
+    }
+
+    pub fn version(mut self, version: i32) -> Self {
+        self.version = version;
+        self
+    }
+
+    pub fn add_input(mut self, txin: TxIn) -> Self {
+        self.inputs.push_back(txin);
+        self
+    }
+
+    pub fn add_output(mut self, public_address: &str, satoshis: u64) -> Self {
+        self.outputs.push_back(TxOut::new(public_address.to_string(), satoshis));
+        self
+    }
+
+    pub fn lock_time(mut self, lock_time: u32) -> Self {
+        self.lock_time = lock_time;
+        self
+    }
+
+    // No inputs is fine, that's how coinbase transactions look here
+    pub fn build(self) -> Result<Transaction, &'static str> {
+        if self.outputs.is_empty() {
+            return Err("bad-txns-vout-empty");
+        }
+        let mut spent = HashSet::new();
+        if !self.inputs.iter().all(|txin| spent.insert((txin.prev_txid.as_str(), txin.out))) {
+            return Err("bad-txns-inputs-duplicate");
+        }
+        let tx = Transaction::with_lock_time(self.version, self.inputs, self.outputs, self.lock_time);
+        tx.check_output_values()?;
+        Ok(tx)
+    }
+}
+
+// Starts out as a genesis block, parent() moves it on top of another block
+#[derive(Default)]
+pub struct BlockBuilder {
+    prev_hash: String,
+    height: u64,
+    timestamp: u64,
+    nonce: u64,
+    transactions: List<Transaction>,
+}
+
+impl BlockBuilder {
+    pub fn new() -> Self {
+        BlockBuilder::default()
+    }
+
+    // Links to `parent` and takes the next height
+    pub fn parent(mut self, parent: &Block) -> Self {
+        self.prev_hash = parent.hash.clone();
+        self.height = parent.height + 1;
+        self
+    }
+
+    pub fn prev_hash(mut self, prev_hash: &str, height: u64) -> Self {
+        self.prev_hash = prev_hash.to_string();
+        self.height = height;
+        self
+    }
+
+    pub fn timestamp(mut self, timestamp: u64) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+
+    pub fn nonce(mut self, nonce: u64) -> Self {
+        self.nonce = nonce;
+        self
+    }
+
+    // Transactions keep the order they're added in
+    pub fn add_tx(mut self, transaction: Transaction) -> Self {
+        self.transactions.push_back(transaction);
+        self
+    }
+
+    pub fn build(self) -> Result<Block, &'static str> {
+        if self.transactions.is_empty() {
+            return Err("bad-blk-length");
+        }
+        // only genesis goes without a parent
+        if (self.height == 0) != self.prev_hash.is_empty() {
+            return Err("bad-prevblk");
+        }
+        let mut txids = HashSet::new();
+        for tx in &self.transactions {
+            // edited after it was built, the merkle root would commit to a stale txid
+            if tx.txid != tx.calculate_txid() {
+                return Err("bad-txnmrklroot");
+            }
+            if !txids.insert(tx.txid.as_str()) {
+                return Err("bad-txns-duplicate");
+            }
+        }
+        let mut block = Block::new(self.prev_hash);
+        block.height = self.height;
+        block.timestamp = self.timestamp;
+        block.nonce = self.nonce;
+        block.transactions = self.transactions;
+        block.hash = block.calculate_hash();
+        Ok(block)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::{BlockChain, MAX_MONEY};
+
+    fn coinbase(address: &str) -> Transaction {
+        TransactionBuilder::new().add_output(address, 50).build().unwrap()
+    }
+
+    #[test]
+    fn test_transaction_builder() {
+        let tx = TransactionBuilder::new()
+            .version(2)
+            .add_input(TxIn::new(String::from("prev"), 1, String::from("signature")))
+            .add_output("a", 10)
+            .add_output("b", 20)
+            .lock_time(500)
+            .build()
+            .unwrap();
+        assert_eq!(tx.version, 2);
+        assert_eq!(tx.lock_time, 500);
+        assert_eq!(tx.outputs.iter().map(|txout| txout.satoshis).collect::<Vec<_>>(), vec![10, 20]);
+        assert_eq!(tx.txid, tx.calculate_txid());
+        assert_eq!(Transaction::deserialize(&tx.serialize()).unwrap().lock_time, 500);
+        // the lock time is part of what the txid commits to
+        let with_lock_time = |lock_time| TransactionBuilder::new().add_output("a", 10).lock_time(lock_time).build().unwrap().txid;
+        assert_ne!(with_lock_time(0), with_lock_time(500));
+    }
+
+    #[test]
+    fn test_transaction_builder_rejects() {
+        assert_eq!(TransactionBuilder::new().build().err(), Some("bad-txns-vout-empty"));
+        let txin = TxIn::new(String::from("prev"), 0, String::new());
+        let duplicate = TransactionBuilder::new().add_input(txin.clone()).add_input(txin).add_output("a", 1);
+        assert_eq!(duplicate.build().err(), Some("bad-txns-inputs-duplicate"));
+        let too_much = TransactionBuilder::new().add_output("a", MAX_MONEY).add_output("b", 1);
+        assert_eq!(too_much.build().err(), Some("bad-txns-txouttotal-toolarge"));
+    }
+
+    #[test]
+    fn test_block_builder() {
+        let genesis = BlockBuilder::new().timestamp(100).add_tx(coinbase("a")).build().unwrap();
+        let block1 = BlockBuilder::new().parent(&genesis).add_tx(coinbase("b")).add_tx(coinbase("c")).build().unwrap();
+        assert_eq!(block1.height, 1);
+        assert_eq!(block1.prev_hash, genesis.hash);
+        assert_eq!(block1.hash, block1.calculate_hash());
+        assert_eq!(block1.txid_at(0), Some(coinbase("b").txid.as_str()));
+
+        let mut chain = BlockChain::new();
+        chain.add_block(genesis);
+        chain.add_block(block1);
+        assert_eq!(chain.get_block_count(), 2);
+    }
+
+    #[test]
+    fn test_block_builder_rejects() {
+        assert_eq!(BlockBuilder::new().build().err(), Some("bad-blk-length"));
+        let orphan = BlockBuilder::new().prev_hash("", 3).add_tx(coinbase("a"));
+        assert_eq!(orphan.build().err(), Some("bad-prevblk"));
+        let twice = BlockBuilder::new().add_tx(coinbase("a")).add_tx(coinbase("a"));
+        assert_eq!(twice.build().err(), Some("bad-txns-duplicate"));
+        let mut edited = coinbase("a");
+        edited.outputs.front_mut().unwrap().satoshis = 1;
+        assert_eq!(BlockBuilder::new().add_tx(edited).build().err(), Some("bad-txnmrklroot"));
+    }
+}
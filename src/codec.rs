@@ -0,0 +1,290 @@
+#![allow(unused)]
+
+/* Binary encoding shared by everything that goes over the wire or to disk:
+   little endian integers, CompactSize counts and length prefixed data */
+
+use std::collections::LinkedList as List;
+use std::fmt;
+
+// Refuse counts that couldn't possibly fit in a block, before trying to read them
+pub const MAX_SIZE: u64 = 0x02000000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DecodeError {
+    UnexpectedEof,
+    // A CompactSize that should have used a shorter form
+    NonCanonicalCompactSize,
+    OversizedCount(u64),
+    InvalidUtf8,
+    TrailingBytes(usize),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::UnexpectedEof => write!(f, "unexpected end of data"),
+            DecodeError::NonCanonicalCompactSize => write!(f, "non-canonical CompactSize"),
+            DecodeError::OversizedCount(count) => write!(f, "count {} is above the limit of {}", count, MAX_SIZE),
+            DecodeError::InvalidUtf8 => write!(f, "string is not valid utf-8"),
+            DecodeError::TrailingBytes(count) => write!(f, "{} bytes left over after decoding", count),
+        }
+    }
+}
+
+pub trait Encodable {
+    fn encode(&self, buf: &mut Vec<u8>);
+}
+
+pub trait Decodable: Sized {
+    fn decode(reader: &mut Reader) -> Result<Self, DecodeError>;
+}
+
+pub fn serialize<T: Encodable + ?Sized>(value: &T) -> Vec<u8> {
+    let mut buf = Vec::new();
+    value.encode(&mut buf);
+    buf
+}
+
+// Decodes a whole buffer, anything left over is an error
+pub fn deserialize<T: Decodable>(data: &[u8]) -> Result<T, DecodeError> {
+    let mut reader = Reader::new(data);
+    let value = T::decode(&mut reader)?;
+    match reader.remaining() {
+        0 => Ok(value),
+        left => Err(DecodeError::TrailingBytes(left)),
+    }
+}
+
+pub struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Reader { data, pos: 0 }
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        let end = self.pos.checked_add(len).ok_or(DecodeError::UnexpectedEof)?;
+        let bytes = self.data.get(self.pos..end).ok_or(DecodeError::UnexpectedEof)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        Ok(self.read_bytes(N)?.try_into().expect("read_bytes returned N bytes"))
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+}
+
+// 1 byte below 0xfd, otherwise a marker byte and a 2, 4 or 8 byte integer
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CompactSize(pub u64);
+
+impl Encodable for CompactSize {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self.0 {
+            n @ 0..=0xfc => buf.push(n as u8),
+            n @ 0xfd..=0xffff => {
+                buf.push(0xfd);
+                buf.extend_from_slice(&(n as u16).to_le_bytes());
+            }
+            n @ 0x10000..=0xffffffff => {
+                buf.push(0xfe);
+                buf.extend_from_slice(&(n as u32).to_le_bytes());
+            }
+            n => {
+                buf.push(0xff);
+                buf.extend_from_slice(&n.to_le_bytes());
+            }
+        }
+    }
+}
+
+impl Decodable for CompactSize {
+    fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let (value, min) = match u8::decode(reader)? {
+            0xfd => (u16::decode(reader)? as u64, 0xfd),
+            0xfe => (u32::decode(reader)? as u64, 0x10000),
+            0xff => (u64::decode(reader)?, 0x100000000),
+            n => return Ok(CompactSize(n as u64)),
+        };
+        if value < min {
+            return Err(DecodeError::NonCanonicalCompactSize);
+        }
+        Ok(CompactSize(value))
+    }
+}
+
+// A count prefix, bounded by MAX_SIZE so a bad length can't make us allocate
+fn read_count(reader: &mut Reader) -> Result<usize, DecodeError> {
+    let CompactSize(count) = CompactSize::decode(reader)?;
+    if count > MAX_SIZE {
+        return Err(DecodeError::OversizedCount(count));
+    }
+    Ok(count as usize)
+}
+
+macro_rules! impl_int {
+    ($($int:ty),*) => {$(
+        impl Encodable for $int {
+            fn encode(&self, buf: &mut Vec<u8>) {
+                buf.extend_from_slice(&self.to_le_bytes());
+            }
+        }
+
+        impl Decodable for $int {
+            fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+                Ok(<$int>::from_le_bytes(reader.read_array()?))
+            }
+        }
+    )*};
+}
+
+impl_int!(u8, u16, u32, u64, i32, i64);
+
+impl<const N: usize> Encodable for [u8; N] {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(self);
+    }
+}
+
+impl<const N: usize> Decodable for [u8; N] {
+    fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        reader.read_array()
+    }
+}
+
+impl<T: Encodable + ?Sized> Encodable for &T {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (**self).encode(buf);
+    }
+}
+
+impl<A: Encodable, B: Encodable> Encodable for (A, B) {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.0.encode(buf);
+        self.1.encode(buf);
+    }
+}
+
+impl<A: Decodable, B: Decodable> Decodable for (A, B) {
+    fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        Ok((A::decode(reader)?, B::decode(reader)?))
+    }
+}
+
+// Length prefixed byte vector
+impl Encodable for [u8] {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        CompactSize(self.len() as u64).encode(buf);
+        buf.extend_from_slice(self);
+    }
+}
+
+impl Encodable for str {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.as_bytes().encode(buf);
+    }
+}
+
+impl Encodable for String {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.as_str().encode(buf);
+    }
+}
+
+impl Decodable for String {
+    fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let len = read_count(reader)?;
+        String::from_utf8(reader.read_bytes(len)?.to_vec()).map_err(|_| DecodeError::InvalidUtf8)
+    }
+}
+
+// Counted sequences. A Vec<u8> encodes exactly like a length prefixed byte vector.
+impl<T: Encodable> Encodable for Vec<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        CompactSize(self.len() as u64).encode(buf);
+        for item in self {
+            item.encode(buf);
+        }
+    }
+}
+
+impl<T: Decodable> Decodable for Vec<T> {
+    fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let count = read_count(reader)?;
+        // no with_capacity, the count hasn't been checked against the data yet
+        let mut items = Vec::new();
+        for _ in 0..count {
+            items.push(T::decode(reader)?);
+        }
+        Ok(items)
+    }
+}
+
+impl<T: Encodable> Encodable for List<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        CompactSize(self.len() as u64).encode(buf);
+        for item in self {
+            item.encode(buf);
+        }
+    }
+}
+
+impl<T: Decodable> Decodable for List<T> {
+    fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let count = read_count(reader)?;
+        let mut items = List::new();
+        for _ in 0..count {
+            items.push_back(T::decode(reader)?);
+        }
+        Ok(items)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compact_size() {
+        for (value, encoded) in [
+            (0, "00"),
+            (0xfc, "fc"),
+            (0xfd, "fdfd00"),
+            (0xffff, "fdffff"),
+            (0x10000, "fe00000100"),
+            (0x100000000, "ff0000000001000000"),
+        ] {
+            assert_eq!(hex::encode(serialize(&CompactSize(value))), encoded);
+            assert_eq!(deserialize::<CompactSize>(&hex::decode(encoded).unwrap()), Ok(CompactSize(value)));
+        }
+        assert_eq!(deserialize::<CompactSize>(&[0xfd, 0xfc, 0x00]), Err(DecodeError::NonCanonicalCompactSize));
+        assert_eq!(deserialize::<CompactSize>(&[0xfe, 0xff, 0xff]), Err(DecodeError::UnexpectedEof));
+    }
+
+    #[test]
+    fn test_integers() {
+        assert_eq!(serialize(&0x01020304u32), vec![4, 3, 2, 1]);
+        assert_eq!(serialize(&-2i32), vec![0xfe, 0xff, 0xff, 0xff]);
+        assert_eq!(deserialize::<u64>(&7u64.to_le_bytes()), Ok(7));
+        assert_eq!(deserialize::<u16>(&[1, 2, 3]), Err(DecodeError::TrailingBytes(1)));
+    }
+
+    #[test]
+    fn test_length_prefixed() {
+        assert_eq!(serialize("abc"), vec![3, b'a', b'b', b'c']);
+        assert_eq!(serialize(&vec![1u8, 2]), serialize(&[1u8, 2][..]));
+        assert_eq!(deserialize::<String>(&[2, b'h', b'i']), Ok(String::from("hi")));
+        assert_eq!(deserialize::<String>(&[2, 0xff, 0xfe]), Err(DecodeError::InvalidUtf8));
+        assert_eq!(deserialize::<Vec<u32>>(&serialize(&vec![5u32, 6])), Ok(vec![5, 6]));
+        // a huge count fails up front instead of trying to allocate
+        let huge = serialize(&CompactSize(u64::MAX));
+        assert_eq!(deserialize::<Vec<u8>>(&huge), Err(DecodeError::OversizedCount(u64::MAX)));
+    }
+}
@@ -0,0 +1,113 @@
+#![allow(unused)]
+
+/* Walking the graph of confirmed transactions: back through the outputs they spend
+   and forward through whoever spent their outputs (the spent-by index) */
+
+use crate::block::{BlockChain, Transaction};
+use crate::store::ChainStore;
+use std::collections::{HashSet, VecDeque};
+
+impl<S: ChainStore> BlockChain<S> {
+    // Transactions whose outputs `txid` spends, their parents and so on up to `depth`
+    // hops back. Breadth first, nearest first, each transaction once.
+    pub fn ancestors(&self, txid: &str, depth: usize) -> Vec<&Transaction> {
+        self.walk(txid, depth, |tx| {
+            tx.inputs
+                .iter()
+                .filter_map(|txin| self.get_transaction(&txin.prev_txid))
+                .collect()
+        })
+    }
+
+    // Transactions spending outputs of `txid`, their children and so on up to `depth` hops on
+    pub fn descendants(&self, txid: &str, depth: usize) -> Vec<&Transaction> {
+        self.walk(txid, depth, |tx| {
+            (0..tx.outputs.len())
+                .filter_map(|vout| self.get_spender(&tx.txid, vout))
+                .filter_map(|(spender, _)| self.get_transaction(spender))
+                .collect()
+        })
+    }
+
+    fn walk<'a>(&'a self, txid: &str, depth: usize, next: impl Fn(&'a Transaction) -> Vec<&'a Transaction>) -> Vec<&'a Transaction> {
+        let mut found = Vec::new();
+        let Some(start) = self.get_transaction(txid) else { return found };
+        let mut seen = HashSet::from([start.txid.as_str()]);
+        let mut queue = VecDeque::from([(start, 0)]);
+        while let Some((tx, hops)) = queue.pop_front() {
+            if hops == depth {
+                continue;
+            }
+            for linked in next(tx) {
+                if seen.insert(linked.txid.as_str()) {
+                    found.push(linked);
+                    queue.push_back((linked, hops + 1));
+                }
+            }
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::{Block, TxIn};
+    use crate::builder::{BlockBuilder, TransactionBuilder};
+
+    fn spend(prev: &[(&Transaction, u32)], outputs: usize, satoshis: u64) -> Transaction {
+        let mut builder = TransactionBuilder::new();
+        for (tx, vout) in prev {
+            builder = builder.add_input(TxIn::new(tx.txid.clone(), *vout, String::from("signature")));
+        }
+        for _ in 0..outputs {
+            builder = builder.add_output("a", satoshis);
+        }
+        builder.build().unwrap()
+    }
+
+    // coinbase -> a (2 outputs) -> b spends a:0, c spends a:1 and b:0
+    fn chain() -> (BlockChain, Vec<Transaction>) {
+        let coinbase = spend(&[], 1, 100);
+        let a = spend(&[(&coinbase, 0)], 2, 40);
+        let b = spend(&[(&a, 0)], 1, 30);
+        let c = spend(&[(&a, 1), (&b, 0)], 1, 60);
+        let genesis = BlockBuilder::new().add_tx(coinbase.clone()).build().unwrap();
+        let block1 = BlockBuilder::new().parent(&genesis).add_tx(a.clone()).add_tx(b.clone()).build().unwrap();
+        let block2 = BlockBuilder::new().parent(&block1).add_tx(c.clone()).build().unwrap();
+        let mut blockchain = BlockChain::new();
+        for block in [genesis, block1, block2] {
+            blockchain.add_block(block);
+        }
+        assert_eq!(blockchain.get_block_count(), 3);
+        (blockchain, vec![coinbase, a, b, c])
+    }
+
+    fn txids(txs: Vec<&Transaction>) -> Vec<&str> {
+        txs.into_iter().map(|tx| tx.txid.as_str()).collect()
+    }
+
+    #[test]
+    fn test_get_spender() {
+        let (mut blockchain, txs) = chain();
+        let [coinbase, a, b, c] = &txs[..] else { unreachable!() };
+        assert_eq!(blockchain.get_spender(&coinbase.txid, 0), Some((a.txid.as_str(), 0)));
+        assert_eq!(blockchain.get_spender(&b.txid, 0), Some((c.txid.as_str(), 1)));
+        assert_eq!(blockchain.get_spender(&c.txid, 0), None);
+        blockchain.disconnect_tip();
+        assert_eq!(blockchain.get_spender(&b.txid, 0), None);
+    }
+
+    #[test]
+    fn test_ancestors_and_descendants() {
+        let (blockchain, txs) = chain();
+        let [coinbase, a, b, c] = &txs[..] else { unreachable!() };
+        assert_eq!(txids(blockchain.ancestors(&c.txid, 1)), vec![a.txid.as_str(), b.txid.as_str()]);
+        // a is reached through both parents but listed once
+        assert_eq!(txids(blockchain.ancestors(&c.txid, 10)), vec![a.txid.as_str(), b.txid.as_str(), coinbase.txid.as_str()]);
+        assert_eq!(txids(blockchain.descendants(&coinbase.txid, 2)), vec![a.txid.as_str(), b.txid.as_str(), c.txid.as_str()]);
+        assert!(blockchain.descendants(&c.txid, 5).is_empty());
+        assert!(blockchain.ancestors(&a.txid, 0).is_empty());
+        assert!(blockchain.ancestors("unknown", 3).is_empty());
+    }
+}
@@ -0,0 +1,195 @@
+#![allow(unused)]
+
+/* Every hash the chain uses in one place: sha256, sha256d, hash160 and BIP340 tagged hashes */
+
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+// A fixed size digest that prints as hex
+macro_rules! hash_type {
+    ($name:ident, $len:expr) => {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        pub struct $name(pub [u8; $len]);
+
+        impl $name {
+            pub fn as_bytes(&self) -> &[u8; $len] {
+                &self.0
+            }
+
+            pub fn to_hex(self) -> String {
+                hex::encode(self.0)
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{}", self.to_hex())
+            }
+        }
+
+        impl AsRef<[u8]> for $name {
+            fn as_ref(&self) -> &[u8] {
+                &self.0
+            }
+        }
+
+        impl From<$name> for [u8; $len] {
+            fn from(hash: $name) -> Self {
+                hash.0
+            }
+        }
+    };
+}
+
+hash_type!(Sha256Hash, 32);
+// Txids, block hashes, merkle nodes and base58 checksums
+hash_type!(Sha256dHash, 32);
+// What P2PKH and P2SH outputs commit to
+hash_type!(Hash160, 20);
+
+pub fn sha256(data: &[u8]) -> Sha256Hash {
+    Sha256Hash(Sha256::digest(data).into())
+}
+
+pub fn sha256d(data: &[u8]) -> Sha256dHash {
+    Sha256dHash(Sha256::digest(Sha256::digest(data)).into())
+}
+
+// RIPEMD160(SHA256(data))
+pub fn hash160(data: &[u8]) -> Hash160 {
+    Hash160(ripemd160(&sha256(data).0))
+}
+
+// BIP340: sha256(sha256(tag) || sha256(tag) || data), so hashes for different
+// purposes can never collide with each other
+pub fn tagged_hash(tag: &str, data: &[u8]) -> Sha256Hash {
+    let tag_hash = Sha256::digest(tag.as_bytes());
+    let mut hasher = Sha256::new();
+    hasher.update(tag_hash);
+    hasher.update(tag_hash);
+    hasher.update(data);
+    Sha256Hash(hasher.finalize().into())
+}
+
+pub fn ripemd160(data: &[u8]) -> [u8; 20] {
+    const R: [usize; 80] = [
+        0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+        7, 4, 13, 1, 10, 6, 15, 3, 12, 0, 9, 5, 2, 14, 11, 8,
+        3, 10, 14, 4, 9, 15, 8, 1, 2, 7, 0, 6, 13, 11, 5, 12,
+        1, 9, 11, 10, 0, 8, 12, 4, 13, 3, 7, 15, 14, 5, 6, 2,
+        4, 0, 5, 9, 7, 12, 2, 10, 14, 1, 3, 8, 11, 6, 15, 13,
+    ];
+    const R_PRIME: [usize; 80] = [
+        5, 14, 7, 0, 9, 2, 11, 4, 13, 6, 15, 8, 1, 10, 3, 12,
+        6, 11, 3, 7, 0, 13, 5, 10, 14, 15, 8, 12, 4, 9, 1, 2,
+        15, 5, 1, 3, 7, 14, 6, 9, 11, 8, 12, 2, 10, 0, 4, 13,
+        8, 6, 4, 1, 3, 11, 15, 0, 5, 12, 2, 13, 9, 7, 10, 14,
+        12, 15, 10, 4, 1, 5, 8, 7, 6, 2, 13, 14, 0, 3, 9, 11,
+    ];
+    const S: [u32; 80] = [
+        11, 14, 15, 12, 5, 8, 7, 9, 11, 13, 14, 15, 6, 7, 9, 8,
+        7, 6, 8, 13, 11, 9, 7, 15, 7, 12, 15, 9, 11, 7, 13, 12,
+        11, 13, 6, 7, 14, 9, 13, 15, 14, 8, 13, 6, 5, 12, 7, 5,
+        11, 12, 14, 15, 14, 15, 9, 8, 9, 14, 5, 6, 8, 6, 5, 12,
+        9, 15, 5, 11, 6, 8, 13, 12, 5, 12, 13, 14, 11, 8, 5, 6,
+    ];
+    const S_PRIME: [u32; 80] = [
+        8, 9, 9, 11, 13, 15, 15, 5, 7, 7, 8, 11, 14, 14, 12, 6,
+        9, 13, 15, 7, 12, 8, 9, 11, 7, 7, 12, 7, 6, 15, 13, 11,
+        9, 7, 15, 11, 8, 6, 6, 14, 12, 13, 5, 14, 13, 13, 7, 5,
+        15, 5, 8, 11, 14, 14, 6, 14, 6, 9, 12, 9, 12, 5, 15, 8,
+        8, 5, 12, 9, 12, 5, 14, 6, 8, 13, 6, 5, 15, 13, 11, 11,
+    ];
+    const K: [u32; 5] = [0x00000000, 0x5A827999, 0x6ED9EBA1, 0x8F1BBCDC, 0xA953FD4E];
+    const K_PRIME: [u32; 5] = [0x50A28BE6, 0x5C4DD124, 0x6D703EF3, 0x7A6D76E9, 0x00000000];
+
+    fn f(round: usize, x: u32, y: u32, z: u32) -> u32 {
+        match round {
+            0 => x ^ y ^ z,
+            1 => (x & y) | (!x & z),
+            2 => (x | !y) ^ z,
+            3 => (x & z) | (y & !z),
+            _ => x ^ (y | !z),
+        }
+    }
+
+    // MD style padding: 0x80, zeros, then the bit length as little endian u64
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64).wrapping_mul(8)).to_le_bytes());
+
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    for chunk in message.chunks(64) {
+        let x: Vec<u32> = chunk.chunks(4).map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]])).collect();
+        let (mut a, mut b, mut c, mut d, mut e) = (h[0], h[1], h[2], h[3], h[4]);
+        let (mut ap, mut bp, mut cp, mut dp, mut ep) = (h[0], h[1], h[2], h[3], h[4]);
+        for j in 0..80 {
+            let round = j / 16;
+            let t = a
+                .wrapping_add(f(round, b, c, d))
+                .wrapping_add(x[R[j]])
+                .wrapping_add(K[round])
+                .rotate_left(S[j])
+                .wrapping_add(e);
+            (a, e, d, c, b) = (e, d, c.rotate_left(10), b, t);
+
+            let t = ap
+                .wrapping_add(f(4 - round, bp, cp, dp))
+                .wrapping_add(x[R_PRIME[j]])
+                .wrapping_add(K_PRIME[round])
+                .rotate_left(S_PRIME[j])
+                .wrapping_add(ep);
+            (ap, ep, dp, cp, bp) = (ep, dp, cp.rotate_left(10), bp, t);
+        }
+        let t = h[1].wrapping_add(c).wrapping_add(dp);
+        h[1] = h[2].wrapping_add(d).wrapping_add(ep);
+        h[2] = h[3].wrapping_add(e).wrapping_add(ap);
+        h[3] = h[4].wrapping_add(a).wrapping_add(bp);
+        h[4] = h[0].wrapping_add(b).wrapping_add(cp);
+        h[0] = t;
+    }
+
+    let mut out = [0u8; 20];
+    for (i, word) in h.iter().enumerate() {
+        out[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sha256() {
+        assert_eq!(sha256(b"").to_hex(), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert_eq!(sha256d(b"").to_hex(), "5df6e0e2761359d30a8275058e299fcc0381534545f55cf43e41983f5d4c9456");
+    }
+
+    #[test]
+    fn test_ripemd160() {
+        assert_eq!(hex::encode(ripemd160(b"")), "9c1185a5c5e9fc54612808977ee8f548b2258d31");
+        assert_eq!(hex::encode(ripemd160(b"abc")), "8eb208f7e05d987a9b044a8e98c6b087f15a0bfc");
+        let long = b"12345678901234567890123456789012345678901234567890123456789012345678901234567890";
+        assert_eq!(hex::encode(ripemd160(long)), "9b752e45573d4b39f4dbd3323cab82bf63326bfb");
+    }
+
+    #[test]
+    fn test_hash160() {
+        // the compressed generator point and its well known P2PKH hash
+        let pubkey = hex::decode("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798").unwrap();
+        assert_eq!(hash160(&pubkey).to_hex(), "751e76e8199196d454941c45d1b3a323f1433bd6");
+    }
+
+    #[test]
+    fn test_tagged_hash() {
+        // hashing with the tag prepended by hand gives the same digest
+        let tag = sha256(b"TapLeaf").0;
+        let manual = sha256(&[&tag[..], &tag[..], b"data"].concat());
+        assert_eq!(tagged_hash("TapLeaf", b"data"), manual);
+        assert_ne!(tagged_hash("TapBranch", b"data"), manual);
+    }
+}
@@ -1 +1,4 @@
-mod mresult;
\ No newline at end of file
+#![allow(non_snake_case)]
+#![cfg_attr(feature = "nightly", feature(try_trait_v2, try_trait_v2_residual))]
+
+pub mod mresult;
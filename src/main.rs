@@ -1,6 +1,30 @@
+#![allow(non_snake_case)]
+#![cfg_attr(feature = "nightly", feature(try_trait_v2, try_trait_v2_residual))]
+
 mod linked_list;
+mod address;
 mod block;
+mod bloom;
+mod builder;
+mod codec;
+mod graph;
+mod hashes;
+mod merkle;
+#[cfg(feature = "metrics")]
+mod metrics;
 mod mresult;
+mod multisig;
+mod policy;
+mod rpc;
+mod script;
+mod shared;
+mod store;
+mod taint;
+mod timedata;
+mod utxo;
+mod validation;
+#[cfg(test)]
+mod testutil;
 
 fn main() {
     println!("Hello, world!");
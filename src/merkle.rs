@@ -0,0 +1,206 @@
+#![allow(unused)]
+
+/* Merkle roots and partial merkle trees (the proof inside a merkleblock), following bitcoin core */
+
+use crate::hashes::{sha256, sha256d};
+
+pub type Hash = [u8; 32];
+
+// Most transactions a block could hold: max block weight over the smallest transaction
+// weight (4 * 60). Proofs claiming more are rejected before walking the tree.
+pub const MAX_TRANSACTIONS: usize = 4_000_000 / (4 * 60);
+
+fn hash_pair(left: &Hash, right: &Hash) -> Hash {
+    let mut data = [0u8; 64];
+    data[..32].copy_from_slice(left);
+    data[32..].copy_from_slice(right);
+    sha256d(&data).0
+}
+
+// Txids are hex encoded sha256 digests, anything else gets hashed so it still fits a leaf
+pub fn txid_bytes(txid: &str) -> Hash {
+    hex::decode(txid)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .unwrap_or_else(|| sha256(txid.as_bytes()).0)
+}
+
+// An odd node at any level is paired with itself. An empty tree has an all zero root.
+pub fn merkle_root(leaves: &[Hash]) -> Hash {
+    if leaves.is_empty() {
+        return [0; 32];
+    }
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| hash_pair(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+            .collect();
+    }
+    level[0]
+}
+
+// Depth first walk of the tree: one bit per visited node (does it have a matched
+// leaf below it) and a hash for every node we don't descend into.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PartialMerkleTree {
+    pub total: usize,
+    pub bits: Vec<bool>,
+    pub hashes: Vec<Hash>,
+}
+
+impl PartialMerkleTree {
+    fn width(total: usize, height: u32) -> usize {
+        total.div_ceil(1 << height)
+    }
+
+    fn height(total: usize) -> u32 {
+        let mut height = 0;
+        while Self::width(total, height) > 1 {
+            height += 1;
+        }
+        height
+    }
+
+    fn calc_hash(&self, height: u32, pos: usize, leaves: &[Hash]) -> Hash {
+        if height == 0 {
+            return leaves[pos];
+        }
+        let left = self.calc_hash(height - 1, pos * 2, leaves);
+        let right = if pos * 2 + 1 < Self::width(self.total, height - 1) {
+            self.calc_hash(height - 1, pos * 2 + 1, leaves)
+        } else {
+            left
+        };
+        hash_pair(&left, &right)
+    }
+
+    fn build(&mut self, height: u32, pos: usize, leaves: &[Hash], matches: &[bool]) {
+        let start = pos << height;
+        let end = ((pos + 1) << height).min(self.total);
+        let parent_of_match = matches[start..end].iter().any(|m| *m);
+        self.bits.push(parent_of_match);
+        if height == 0 || !parent_of_match {
+            let hash = self.calc_hash(height, pos, leaves);
+            self.hashes.push(hash);
+        } else {
+            self.build(height - 1, pos * 2, leaves, matches);
+            if pos * 2 + 1 < Self::width(self.total, height - 1) {
+                self.build(height - 1, pos * 2 + 1, leaves, matches);
+            }
+        }
+    }
+
+    pub fn new(leaves: &[Hash], matches: &[bool]) -> Self {
+        let mut tree = PartialMerkleTree {
+            total: leaves.len(),
+            bits: Vec::new(),
+            hashes: Vec::new(),
+        };
+        if !leaves.is_empty() {
+            tree.build(Self::height(leaves.len()), 0, leaves, matches);
+        }
+        tree
+    }
+
+    fn extract(&self, height: u32, pos: usize, bits_used: &mut usize, hashes_used: &mut usize, matched: &mut Vec<Hash>) -> Option<Hash> {
+        let parent_of_match = *self.bits.get(*bits_used)?;
+        *bits_used += 1;
+        if height == 0 || !parent_of_match {
+            let hash = *self.hashes.get(*hashes_used)?;
+            *hashes_used += 1;
+            if height == 0 && parent_of_match {
+                matched.push(hash);
+            }
+            return Some(hash);
+        }
+        let left = self.extract(height - 1, pos * 2, bits_used, hashes_used, matched)?;
+        let right = if pos * 2 + 1 < Self::width(self.total, height - 1) {
+            let right = self.extract(height - 1, pos * 2 + 1, bits_used, hashes_used, matched)?;
+            // identical siblings would allow the duplicate transaction trick (CVE-2012-2459)
+            if right == left {
+                return None;
+            }
+            right
+        } else {
+            left
+        };
+        Some(hash_pair(&left, &right))
+    }
+
+    // Recompute the root and collect the matched leaves. None if the tree is malformed.
+    pub fn extract_matches(&self) -> Option<(Hash, Vec<Hash>)> {
+        if self.total == 0 || self.total > MAX_TRANSACTIONS {
+            return None;
+        }
+        if self.hashes.len() > self.total || self.bits.len() < self.hashes.len() {
+            return None;
+        }
+        let mut bits_used = 0;
+        let mut hashes_used = 0;
+        let mut matched = Vec::new();
+        let root = self.extract(Self::height(self.total), 0, &mut bits_used, &mut hashes_used, &mut matched)?;
+        // everything must be consumed, bits are padded to whole bytes on the wire
+        if bits_used.div_ceil(8) != self.bits.len().div_ceil(8) || hashes_used != self.hashes.len() {
+            return None;
+        }
+        Some((root, matched))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaves(n: u8) -> Vec<Hash> {
+        (0..n).map(|i| [i; 32]).collect()
+    }
+
+    #[test]
+    fn test_merkle_root() {
+        assert_eq!(merkle_root(&[]), [0; 32]);
+        assert_eq!(merkle_root(&[[7; 32]]), [7; 32]); // a single leaf is its own root
+        let three = leaves(3);
+        let expected = hash_pair(&hash_pair(&three[0], &three[1]), &hash_pair(&three[2], &three[2]));
+        assert_eq!(merkle_root(&three), expected);
+    }
+
+    #[test]
+    fn test_partial_tree_round_trip() {
+        for n in 1..20u8 {
+            let leaves = leaves(n);
+            let root = merkle_root(&leaves);
+            for matched in 0..n as usize {
+                let matches: Vec<bool> = (0..n as usize).map(|i| i == matched || i % 5 == 4).collect();
+                let tree = PartialMerkleTree::new(&leaves, &matches);
+                let (extracted_root, found) = tree.extract_matches().unwrap();
+                assert_eq!(extracted_root, root);
+                let expected: Vec<Hash> = leaves.iter().zip(&matches).filter(|(_, m)| **m).map(|(l, _)| *l).collect();
+                assert_eq!(found, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_tampered_tree_fails() {
+        let leaves = leaves(7);
+        let mut matches = vec![false; 7];
+        matches[3] = true;
+        let mut tree = PartialMerkleTree::new(&leaves, &matches);
+        tree.hashes[0][0] ^= 1;
+        let (root, _) = tree.extract_matches().unwrap();
+        assert_ne!(root, merkle_root(&leaves));
+
+        let mut tree = PartialMerkleTree::new(&leaves, &matches);
+        tree.hashes.pop();
+        assert!(tree.extract_matches().is_none());
+    }
+
+    #[test]
+    fn test_malformed_total_fails() {
+        for total in [usize::MAX, MAX_TRANSACTIONS + 1] {
+            let tree = PartialMerkleTree { total, bits: vec![true], hashes: vec![[0; 32]] };
+            assert!(tree.extract_matches().is_none());
+        }
+    }
+}
@@ -0,0 +1,100 @@
+#![allow(unused)]
+
+/* Prometheus text exposition of chain internals. Enable with `--features metrics`. */
+
+use crate::block::BlockChain;
+use crate::shared::SharedChain;
+use crate::store::ChainStore;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// Requests are answered one at a time, so a slow or silent client can only hold the
+// exporter up this long, and can't make it buffer more than this much
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_REQUEST_BYTES: u64 = 8192;
+
+fn metric(out: &mut String, name: &str, kind: &str, help: &str, value: impl std::fmt::Display) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+pub fn render<S: ChainStore>(chain: &BlockChain<S>) -> String {
+    let stats = chain.get_chain_stats();
+    let filter = chain.get_txid_filter_stats();
+    let (lookups, misses) = chain.get_txid_filter_hits();
+    let mut out = String::new();
+
+    metric(&mut out, "chain_blocks", "gauge", "Blocks in the active chain", chain.get_block_count());
+    metric(&mut out, "chain_utxos", "gauge", "Unspent transaction outputs", chain.get_utxo_count());
+    metric(&mut out, "chain_time_offset_seconds", "gauge", "Network adjusted time offset", chain.get_time_offset());
+    metric(&mut out, "chain_blocks_connected_total", "counter", "Blocks connected", stats.blocks_connected);
+    metric(&mut out, "chain_blocks_rejected_total", "counter", "Blocks rejected as invalid", stats.blocks_rejected);
+    metric(&mut out, "chain_blocks_disconnected_total", "counter", "Blocks disconnected", stats.blocks_disconnected);
+    metric(&mut out, "chain_rollbacks_total", "counter", "Rollbacks of one or more blocks", stats.rollbacks);
+    metric(&mut out, "chain_max_rollback_depth", "gauge", "Deepest rollback seen", stats.max_rollback_depth);
+    metric(&mut out, "txid_filter_items", "gauge", "Txids in the bloom filter", filter.items);
+    metric(&mut out, "txid_filter_lookups_total", "counter", "Transaction lookups", lookups);
+    metric(&mut out, "txid_filter_misses_total", "counter", "Lookups answered by the filter alone", misses);
+    out
+}
+
+// Answers every HTTP request on `addr` with the current metrics. Runs on its own
+// thread and only takes the chain's read lock while rendering.
+pub fn serve(addr: impl ToSocketAddrs, chain: SharedChain) -> io::Result<(SocketAddr, JoinHandle<()>)> {
+    let listener = TcpListener::bind(addr)?;
+    let local_addr = listener.local_addr()?;
+    let handle = thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            if stream.set_read_timeout(Some(READ_TIMEOUT)).is_err() {
+                continue;
+            }
+            // we don't route, just drain the request line and headers
+            let mut reader = BufReader::new((&stream).take(MAX_REQUEST_BYTES));
+            let mut line = String::new();
+            while reader.read_line(&mut line).map(|n| n > 2).unwrap_or(false) {
+                line.clear();
+            }
+            let body = render(&chain.read());
+            let _ = write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+        }
+    });
+    Ok((local_addr, handle))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::Block;
+    use std::io::Read;
+    use std::net::TcpStream;
+
+    #[test]
+    fn test_render() {
+        let mut chain = BlockChain::new();
+        chain.add_block(Block::new(String::new()));
+        let text = render(&chain);
+        assert!(text.contains("# TYPE chain_blocks gauge\nchain_blocks 1\n"));
+        assert!(text.contains("chain_blocks_connected_total 1\n"));
+    }
+
+    #[test]
+    fn test_serve() {
+        let (addr, _) = serve("127.0.0.1:0", SharedChain::new(BlockChain::new())).unwrap();
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("chain_blocks 0"));
+    }
+}
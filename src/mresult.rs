@@ -1,21 +1,26 @@
 #![allow(unused)]
 
-enum MResult<T, E> {
+#[cfg(feature = "nightly")]
+use std::convert::Infallible;
+#[cfg(feature = "nightly")]
+use std::ops::{ControlFlow, FromResidual, Residual, Try};
+
+pub enum MResult<T, E> {
     Ok(T),
     Err(E),
 }
 
 impl<T, E> MResult<T, E> {
-    fn ok(value: T) -> Self {
+    pub fn ok(value: T) -> Self {
         MResult::Ok(value)
     }
     // Function to create an Err variant
-    fn err(error: E) -> Self {
+    pub fn err(error: E) -> Self {
         MResult::Err(error)
     }
 
     // Method to check if it's an Ok variant
-    fn is_ok(&self) -> bool {
+    pub fn is_ok(&self) -> bool {
         match self {
             MResult::Ok(_) => true,
             MResult::Err(_) =>  false
@@ -23,7 +28,7 @@ impl<T, E> MResult<T, E> {
     }
 
     // Method to check if it's an Err variant
-    fn is_err(&self) -> bool {
+    pub fn is_err(&self) -> bool {
         match self {
             MResult::Err(_) => true,
             MResult::Ok(_) =>  false
@@ -31,7 +36,7 @@ impl<T, E> MResult<T, E> {
     }
 
     // Method to unwrap the Ok value, panics if it's an Err
-    fn unwrap(self) -> T {
+    pub fn unwrap(self) -> T {
         match self {
             MResult::Ok(value) => value,
             MResult::Err(_) => panic!("Error value"),
@@ -39,12 +44,241 @@ impl<T, E> MResult<T, E> {
     }
 
     // Method to unwrap the Err value, panics if it's an Ok
-    fn unwrap_err(self) -> E {
+    pub fn unwrap_err(self) -> E {
         match self {
             MResult::Ok(_) => panic!("Ok Value"),
             MResult::Err(error) => error,
         }
     }
+
+    // Method to borrow the contained value without consuming the result
+    pub fn as_ref(&self) -> MResult<&T, &E> {
+        match self {
+            MResult::Ok(value) => MResult::Ok(value),
+            MResult::Err(error) => MResult::Err(error),
+        }
+    }
+
+    // Same as as_ref but the borrow is mutable
+    pub fn as_mut(&mut self) -> MResult<&mut T, &mut E> {
+        match self {
+            MResult::Ok(value) => MResult::Ok(value),
+            MResult::Err(error) => MResult::Err(error),
+        }
+    }
+
+    // Method to apply f to the Ok value, or return default if it's an Err
+    pub fn map_or<U, F: FnOnce(T) -> U>(self, default: U, f: F) -> U {
+        match self {
+            MResult::Ok(value) => f(value),
+            MResult::Err(_) => default,
+        }
+    }
+
+    // Method to apply f to the Ok value, or default_fn to the Err value
+    pub fn map_or_else<U, D: FnOnce(E) -> U, F: FnOnce(T) -> U>(self, default_fn: D, f: F) -> U {
+        match self {
+            MResult::Ok(value) => f(value),
+            MResult::Err(error) => default_fn(error),
+        }
+    }
+
+    // Method to iterate over the Ok value, yields nothing if it's an Err
+    pub fn iter(&self) -> std::option::IntoIter<&T> {
+        match self {
+            MResult::Ok(value) => Some(value).into_iter(),
+            MResult::Err(_) => None.into_iter(),
+        }
+    }
+
+    // Same as iter but yields a mutable reference
+    pub fn iter_mut(&mut self) -> std::option::IntoIter<&mut T> {
+        match self {
+            MResult::Ok(value) => Some(value).into_iter(),
+            MResult::Err(_) => None.into_iter(),
+        }
+    }
+
+    /// Whether the result is an Ok holding a value equal to x
+    ///
+    /// ```
+    /// use Bip_basics::mresult::MResult;
+    /// let ok: MResult<u32, &str> = MResult::ok(2);
+    /// assert!(ok.contains(&2));
+    /// assert!(!ok.contains(&3));
+    /// ```
+    pub fn contains<U: PartialEq<T>>(&self, x: &U) -> bool {
+        match self {
+            MResult::Ok(value) => x == value,
+            MResult::Err(_) => false,
+        }
+    }
+
+    /// Whether the result is an Err holding an error equal to f
+    ///
+    /// ```
+    /// use Bip_basics::mresult::MResult;
+    /// let err: MResult<u32, &str> = MResult::err("bad");
+    /// assert!(err.contains_err(&"bad"));
+    /// assert!(!MResult::<u32, &str>::ok(1).contains_err(&"bad"));
+    /// ```
+    pub fn contains_err<F: PartialEq<E>>(&self, f: &F) -> bool {
+        match self {
+            MResult::Ok(_) => false,
+            MResult::Err(error) => f == error,
+        }
+    }
+
+    /// Pairs two Ok values, or returns the first error
+    ///
+    /// ```
+    /// use Bip_basics::mresult::MResult;
+    /// let a: MResult<u32, &str> = MResult::ok(1);
+    /// let b: MResult<&str, &str> = MResult::ok("one");
+    /// assert_eq!(a.zip(b).unwrap(), (1, "one"));
+    ///
+    /// let a: MResult<u32, &str> = MResult::err("first");
+    /// let b: MResult<u32, &str> = MResult::err("second");
+    /// assert_eq!(a.zip(b).unwrap_err(), "first");
+    /// ```
+    pub fn zip<U>(self, other: MResult<U, E>) -> MResult<(T, U), E> {
+        match (self, other) {
+            (MResult::Ok(value), MResult::Ok(other)) => MResult::Ok((value, other)),
+            (MResult::Err(error), _) | (_, MResult::Err(error)) => MResult::Err(error),
+        }
+    }
+
+    /// The Ok value, or T's default if it's an Err
+    ///
+    /// ```
+    /// use Bip_basics::mresult::MResult;
+    /// let ok: MResult<u32, &str> = MResult::ok(7);
+    /// let err: MResult<u32, &str> = MResult::err("bad");
+    /// assert_eq!(ok.unwrap_or_default(), 7);
+    /// assert_eq!(err.unwrap_or_default(), 0);
+    /// ```
+    pub fn unwrap_or_default(self) -> T
+    where
+        T: Default,
+    {
+        match self {
+            MResult::Ok(value) => value,
+            MResult::Err(_) => T::default(),
+        }
+    }
+}
+
+impl<T, E> MResult<MResult<T, E>, E> {
+    /// Removes one level of nesting, an error at either level comes through
+    ///
+    /// ```
+    /// use Bip_basics::mresult::MResult;
+    /// let nested: MResult<MResult<u32, &str>, &str> = MResult::ok(MResult::ok(3));
+    /// assert_eq!(nested.flatten().unwrap(), 3);
+    ///
+    /// let inner_err: MResult<MResult<u32, &str>, &str> = MResult::ok(MResult::err("inner"));
+    /// assert_eq!(inner_err.flatten().unwrap_err(), "inner");
+    /// ```
+    pub fn flatten(self) -> MResult<T, E> {
+        match self {
+            MResult::Ok(inner) => inner,
+            MResult::Err(error) => MResult::Err(error),
+        }
+    }
+}
+
+// With the nightly feature ? works on MResult directly, converting the error with From like Result does
+#[cfg(feature = "nightly")]
+impl<T, E> Try for MResult<T, E> {
+    type Output = T;
+    type Residual = MResult<Infallible, E>;
+
+    fn from_output(output: T) -> Self {
+        MResult::Ok(output)
+    }
+
+    fn branch(self) -> ControlFlow<Self::Residual, T> {
+        match self {
+            MResult::Ok(value) => ControlFlow::Continue(value),
+            MResult::Err(error) => ControlFlow::Break(MResult::Err(error)),
+        }
+    }
+}
+
+#[cfg(feature = "nightly")]
+impl<T, E, F: From<E>> FromResidual<MResult<Infallible, E>> for MResult<T, F> {
+    fn from_residual(residual: MResult<Infallible, E>) -> Self {
+        match residual {
+            MResult::Ok(never) => match never {},
+            MResult::Err(error) => MResult::Err(From::from(error)),
+        }
+    }
+}
+
+#[cfg(feature = "nightly")]
+impl<T, E> Residual<T> for MResult<Infallible, E> {
+    type TryType = MResult<T, E>;
+}
+
+/// Stable stand-in for `?` on MResult: evaluates to the Ok value, or returns
+/// the error (converted with From) from the enclosing function
+///
+/// ```
+/// use Bip_basics::mresult::MResult;
+/// use Bip_basics::mtry;
+///
+/// fn half(n: u32) -> MResult<u32, String> {
+///     if n % 2 == 0 { MResult::ok(n / 2) } else { MResult::err(format!("{} is odd", n)) }
+/// }
+///
+/// fn quarter(n: u32) -> MResult<u32, String> {
+///     let h = mtry!(half(n));
+///     half(h)
+/// }
+///
+/// assert_eq!(quarter(8).unwrap(), 2);
+/// assert_eq!(quarter(6).unwrap_err(), "3 is odd");
+/// ```
+#[macro_export]
+macro_rules! mtry {
+    ($expr:expr) => {
+        match $expr {
+            $crate::mresult::MResult::Ok(value) => value,
+            $crate::mresult::MResult::Err(error) => {
+                return $crate::mresult::MResult::Err(::core::convert::From::from(error))
+            }
+        }
+    };
+}
+
+impl<T, E> IntoIterator for MResult<T, E> {
+    type Item = T;
+    type IntoIter = std::option::IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        match self {
+            MResult::Ok(value) => Some(value).into_iter(),
+            MResult::Err(_) => None.into_iter(),
+        }
+    }
+}
+
+impl<'a, T, E> IntoIterator for &'a MResult<T, E> {
+    type Item = &'a T;
+    type IntoIter = std::option::IntoIter<&'a T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T, E> IntoIterator for &'a mut MResult<T, E> {
+    type Item = &'a mut T;
+    type IntoIter = std::option::IntoIter<&'a mut T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
 }
 
 // Add unit tests below
@@ -53,6 +287,7 @@ mod test {
     use crate::mresult::MResult;
 
     #[test]
+    #[allow(irrefutable_let_patterns)]
     fn create_ok(){
         let ok:MResult<String,String> = MResult::ok("Success".to_string());
 
@@ -80,5 +315,139 @@ mod test {
         assert_eq!(result.unwrap_err(), "error");
     }
 
+    #[test]
+    fn test_as_ref() {
+        let ok: MResult<String, String> = MResult::ok("value".to_string());
+        assert_eq!(ok.as_ref().unwrap().len(), 5);
+        // ok is still usable since as_ref did not move it
+        assert_eq!(ok.unwrap(), "value");
+
+        let err: MResult<String, String> = MResult::err("error".to_string());
+        assert_eq!(err.as_ref().unwrap_err(), "error");
+    }
+
+    #[test]
+    fn test_as_mut() {
+        let mut ok: MResult<i32, i32> = MResult::ok(1);
+        if let MResult::Ok(value) = ok.as_mut() {
+            *value = 10;
+        }
+        assert_eq!(ok.unwrap(), 10);
+
+        let mut err: MResult<i32, i32> = MResult::err(1);
+        if let MResult::Err(error) = err.as_mut() {
+            *error = 20;
+        }
+        assert_eq!(err.unwrap_err(), 20);
+    }
+
+    #[test]
+    fn test_map_or() {
+        let ok: MResult<&str, &str> = MResult::ok("foo");
+        assert_eq!(ok.map_or(42, |v| v.len()), 3);
+        let err: MResult<&str, &str> = MResult::err("bar");
+        assert_eq!(err.map_or(42, |v| v.len()), 42);
+    }
+
+    #[test]
+    fn test_map_or_else() {
+        let ok: MResult<i32, &str> = MResult::ok(2);
+        assert_eq!(ok.map_or_else(|e| e.len() as i32, |v| v * 2), 4);
+        let err: MResult<i32, &str> = MResult::err("error");
+        assert_eq!(err.map_or_else(|e| e.len() as i32, |v| v * 2), 5);
+    }
+
+    #[test]
+    fn test_iter() {
+        let ok: MResult<i32, &str> = MResult::ok(1);
+        let err: MResult<i32, &str> = MResult::err("error");
+        assert_eq!(ok.iter().collect::<Vec<_>>(), vec![&1]);
+        assert_eq!(err.iter().count(), 0);
+    }
+
+    #[test]
+    fn test_iter_mut() {
+        let mut ok: MResult<i32, &str> = MResult::ok(1);
+        for value in ok.iter_mut() {
+            *value += 1;
+        }
+        assert_eq!(ok.unwrap(), 2);
+    }
+
+    #[test]
+    fn test_into_iter() {
+        let results: Vec<MResult<i32, &str>> = vec![MResult::ok(1), MResult::err("error"), MResult::ok(3)];
+        let values: Vec<i32> = results.into_iter().flatten().collect();
+        assert_eq!(values, vec![1, 3]);
+
+        let ok: MResult<i32, &str> = MResult::ok(5);
+        let mut sum = 0;
+        for value in &ok {
+            sum += value;
+        }
+        assert_eq!(sum, 5);
+    }
+
+    fn parse(input: &str) -> MResult<i64, String> {
+        match input.parse() {
+            Ok(value) => MResult::ok(value),
+            Err(_) => MResult::err(format!("{} is not a number", input)),
+        }
+    }
+
+    fn parse_and_double(input: &str) -> MResult<i64, String> {
+        let value = crate::mtry!(parse(input));
+        MResult::ok(value * 2)
+    }
+
+    #[test]
+    fn test_mtry() {
+        assert_eq!(parse_and_double("21").unwrap(), 42);
+        assert!(parse_and_double("abc").is_err());
+    }
+
+    #[cfg(feature = "nightly")]
+    #[test]
+    fn test_question_mark() {
+        #[derive(Debug, PartialEq)]
+        struct Wrapped(&'static str);
+        impl From<&'static str> for Wrapped {
+            fn from(error: &'static str) -> Self {
+                Wrapped(error)
+            }
+        }
+
+        fn add(a: MResult<i32, &'static str>, b: MResult<i32, &'static str>) -> MResult<i32, Wrapped> {
+            MResult::ok(a? + b?)
+        }
+        assert_eq!(add(MResult::ok(1), MResult::ok(2)).unwrap(), 3);
+        assert_eq!(add(MResult::ok(1), MResult::err("bad")).unwrap_err(), Wrapped("bad"));
+    }
+
+    #[test]
+    fn test_flatten() {
+        let outer_err: MResult<MResult<i32, &str>, &str> = MResult::err("outer");
+        assert_eq!(outer_err.flatten().unwrap_err(), "outer");
+    }
+
+    #[test]
+    fn test_contains() {
+        let ok: MResult<String, String> = MResult::ok("value".to_string());
+        assert!(ok.contains(&"value"));
+        assert!(!ok.contains_err(&"value"));
+    }
+
+    #[test]
+    fn test_zip() {
+        let a: MResult<i32, &str> = MResult::ok(1);
+        let b: MResult<i32, &str> = MResult::err("second");
+        assert_eq!(a.zip(b).unwrap_err(), "second");
+    }
+
+    #[test]
+    fn test_unwrap_or_default() {
+        let err: MResult<String, i32> = MResult::err(1);
+        assert_eq!(err.unwrap_or_default(), "");
+    }
 
 }
@@ -0,0 +1,226 @@
+#![allow(unused)]
+
+/* createmultisig: m-of-n scripts, their addresses and descriptors */
+
+use crate::address::{script_to_address, Network};
+use crate::hashes::{hash160, sha256};
+use crate::script::ScriptBuilder;
+use std::fmt;
+
+// Consensus limit on a pushed P2SH redeem script
+pub const MAX_SCRIPT_ELEMENT_SIZE: usize = 520;
+// Above 16 the small integer opcodes run out, bitcoin core doesn't build those either
+pub const MAX_STANDARD_MULTISIG_KEYS: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MultisigType {
+    Legacy,     // P2SH
+    P2shSegwit, // P2WSH nested in P2SH
+    Bech32,     // P2WSH
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Multisig {
+    pub address: String,
+    pub redeem_script: String, // hex, what the P2SH output commits to (if any)
+    pub witness_script: Option<String>, // hex, the multisig script for segwit types
+    pub descriptor: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MultisigError {
+    InvalidThreshold { required: usize, keys: usize },
+    TooManyKeys(usize),
+    InvalidPubkey(usize), // index of the bad key
+    UncompressedKeyInSegwit(usize),
+    ScriptTooLarge(usize),
+}
+
+impl fmt::Display for MultisigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MultisigError::InvalidThreshold { required, keys } => {
+                write!(f, "need between 1 and {} signatures, got {}", keys, required)
+            }
+            MultisigError::TooManyKeys(keys) => write!(f, "at most {} keys, got {}", MAX_STANDARD_MULTISIG_KEYS, keys),
+            MultisigError::InvalidPubkey(index) => write!(f, "key {} is not a valid public key", index),
+            MultisigError::UncompressedKeyInSegwit(index) => write!(f, "key {} is uncompressed, segwit requires compressed keys", index),
+            MultisigError::ScriptTooLarge(size) => write!(f, "redeem script is {} bytes, limit is {}", size, MAX_SCRIPT_ELEMENT_SIZE),
+        }
+    }
+}
+
+fn parse_pubkey(key: &str) -> Option<Vec<u8>> {
+    let bytes = hex::decode(key).ok()?;
+    match (bytes.len(), bytes.first()) {
+        (33, Some(0x02 | 0x03)) | (65, Some(0x04)) => Some(bytes),
+        _ => None,
+    }
+}
+
+pub fn multisig_script(required: usize, pubkeys: &[Vec<u8>]) -> Vec<u8> {
+    ScriptBuilder::multisig(required, pubkeys)
+}
+
+fn p2sh_script(redeem_script: &[u8]) -> Vec<u8> {
+    ScriptBuilder::p2sh(hash160(redeem_script).as_bytes())
+}
+
+fn p2wsh_script(witness_script: &[u8]) -> Vec<u8> {
+    ScriptBuilder::p2wsh(sha256(witness_script).as_bytes())
+}
+
+// Output descriptor checksum (BIP380)
+pub fn descriptor_checksum(descriptor: &str) -> Option<String> {
+    const INPUT_CHARSET: &str = "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+    const CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+    fn polymod(c: u64, value: u64) -> u64 {
+        let c0 = c >> 35;
+        let mut c = ((c & 0x7ffffffff) << 5) ^ value;
+        for (bit, generator) in [0xf5dee51989, 0xa9fdca3312, 0x1bab10e32d, 0x3706b1677a, 0x644d626ffd].iter().enumerate() {
+            if (c0 >> bit) & 1 == 1 {
+                c ^= generator;
+            }
+        }
+        c
+    }
+
+    let mut c: u64 = 1;
+    let mut class: u64 = 0;
+    let mut class_count = 0;
+    for ch in descriptor.chars() {
+        let pos = INPUT_CHARSET.find(ch)? as u64;
+        c = polymod(c, pos & 31);
+        class = class * 3 + (pos >> 5);
+        class_count += 1;
+        if class_count == 3 {
+            c = polymod(c, class);
+            class = 0;
+            class_count = 0;
+        }
+    }
+    if class_count > 0 {
+        c = polymod(c, class);
+    }
+    for _ in 0..8 {
+        c = polymod(c, 0);
+    }
+    c ^= 1;
+    Some((0..8).map(|j| CHECKSUM_CHARSET[((c >> (5 * (7 - j))) & 31) as usize] as char).collect())
+}
+
+pub fn create_multisig(required: usize, pubkeys: &[&str], kind: MultisigType, network: Network) -> Result<Multisig, MultisigError> {
+    if pubkeys.len() > MAX_STANDARD_MULTISIG_KEYS {
+        return Err(MultisigError::TooManyKeys(pubkeys.len()));
+    }
+    if required == 0 || required > pubkeys.len() {
+        return Err(MultisigError::InvalidThreshold { required, keys: pubkeys.len() });
+    }
+    let mut keys = Vec::with_capacity(pubkeys.len());
+    for (index, key) in pubkeys.iter().enumerate() {
+        let key = parse_pubkey(key).ok_or(MultisigError::InvalidPubkey(index))?;
+        if kind != MultisigType::Legacy && key.len() != 33 {
+            return Err(MultisigError::UncompressedKeyInSegwit(index));
+        }
+        keys.push(key);
+    }
+
+    let script = multisig_script(required, &keys);
+    let multi = format!("multi({},{})", required, pubkeys.join(","));
+    let (redeem_script, witness_script, output_script, descriptor) = match kind {
+        MultisigType::Legacy => {
+            if script.len() > MAX_SCRIPT_ELEMENT_SIZE {
+                return Err(MultisigError::ScriptTooLarge(script.len()));
+            }
+            (script.clone(), None, p2sh_script(&script), format!("sh({})", multi))
+        }
+        MultisigType::P2shSegwit => {
+            let redeem = p2wsh_script(&script);
+            (redeem.clone(), Some(script), p2sh_script(&redeem), format!("sh(wsh({}))", multi))
+        }
+        MultisigType::Bech32 => {
+            let output = p2wsh_script(&script);
+            (script.clone(), Some(script), output, format!("wsh({})", multi))
+        }
+    };
+
+    // every script built above is standard so these can't fail
+    let address = script_to_address(&output_script, network).expect("standard script has an address");
+    let checksum = descriptor_checksum(&descriptor).expect("descriptor uses valid characters");
+    Ok(Multisig {
+        address,
+        redeem_script: hex::encode(redeem_script),
+        witness_script: witness_script.map(hex::encode),
+        descriptor: format!("{}#{}", descriptor, checksum),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::{validate_address, AddressType};
+
+    const KEY1: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+    const KEY2: &str = "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5";
+    const UNCOMPRESSED: &str = "0479be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8";
+
+    #[test]
+    fn test_descriptor_checksum() {
+        // vectors from BIP380
+        assert_eq!(descriptor_checksum("raw(deadbeef)").as_deref(), Some("89f8spxm"));
+        assert_eq!(descriptor_checksum("addr(mkmZxiEcEd8ZqjQWVZuC6so5dFMKEFpN2j)").as_deref(), Some("02wpgw69"));
+        assert_eq!(descriptor_checksum("raw(deadbeef)\u{e9}"), None);
+    }
+
+    #[test]
+    fn test_legacy_multisig() {
+        let multisig = create_multisig(1, &[KEY1, KEY2], MultisigType::Legacy, Network::Mainnet).unwrap();
+        assert_eq!(multisig.redeem_script, format!("5121{}21{}52ae", KEY1, KEY2));
+        assert_eq!(multisig.witness_script, None);
+        let info = validate_address(&multisig.address).unwrap();
+        assert_eq!(info.address_type, AddressType::P2sh);
+        assert_eq!(info.payload, hash160(&hex::decode(&multisig.redeem_script).unwrap()).0);
+        let descriptor = format!("sh(multi(1,{},{}))", KEY1, KEY2);
+        assert_eq!(multisig.descriptor, format!("{}#{}", descriptor, descriptor_checksum(&descriptor).unwrap()));
+    }
+
+    #[test]
+    fn test_segwit_multisig() {
+        let multisig = create_multisig(2, &[KEY1, KEY2], MultisigType::Bech32, Network::Regtest).unwrap();
+        assert!(multisig.address.starts_with("bcrt1q"));
+        assert_eq!(validate_address(&multisig.address).unwrap().address_type, AddressType::P2wsh);
+        assert!(multisig.descriptor.starts_with("wsh(multi(2,"));
+
+        let nested = create_multisig(2, &[KEY1, KEY2], MultisigType::P2shSegwit, Network::Mainnet).unwrap();
+        assert!(nested.address.starts_with('3'));
+        assert_eq!(nested.witness_script, multisig.witness_script);
+        assert!(nested.redeem_script.starts_with("0020"));
+    }
+
+    #[test]
+    fn test_multisig_errors() {
+        assert_eq!(
+            create_multisig(3, &[KEY1, KEY2], MultisigType::Legacy, Network::Mainnet),
+            Err(MultisigError::InvalidThreshold { required: 3, keys: 2 })
+        );
+        assert_eq!(
+            create_multisig(1, &[KEY1, "02abcd"], MultisigType::Legacy, Network::Mainnet),
+            Err(MultisigError::InvalidPubkey(1))
+        );
+        assert_eq!(
+            create_multisig(1, &[UNCOMPRESSED], MultisigType::Bech32, Network::Mainnet),
+            Err(MultisigError::UncompressedKeyInSegwit(0))
+        );
+        assert!(create_multisig(1, &[UNCOMPRESSED], MultisigType::Legacy, Network::Mainnet).is_ok());
+        assert_eq!(
+            create_multisig(1, &[KEY1; 17], MultisigType::Legacy, Network::Mainnet),
+            Err(MultisigError::TooManyKeys(17))
+        );
+        // 16 uncompressed keys don't fit in 520 bytes
+        assert_eq!(
+            create_multisig(1, &[UNCOMPRESSED; 16], MultisigType::Legacy, Network::Mainnet),
+            Err(MultisigError::ScriptTooLarge(3 + 16 * 66))
+        );
+    }
+}
@@ -0,0 +1,342 @@
+#![allow(unused)]
+
+/* A small spending policy language that compiles to script, miniscript at teaching scale:
+     pk(<hex pubkey>)        signature from the key
+     older(<blocks>)         relative timelock (CSV)
+     after(<height>)         absolute timelock (CLTV)
+     sha256(<hex hash>)      preimage of the hash
+     and(X,Y)  or(X,Y)
+*/
+
+use crate::script::{
+    decode_script_num, instructions, Instruction, ScriptBuilder, MAX_LOCKTIME_NUM_SIZE, MAX_SCRIPT_NUM_SIZE, OP_1, OP_16, OP_CHECKLOCKTIMEVERIFY,
+    OP_CHECKSEQUENCEVERIFY, OP_CHECKSIG, OP_CHECKSIGVERIFY, OP_ELSE, OP_ENDIF, OP_EQUAL, OP_EQUALVERIFY, OP_IF, OP_SHA256,
+    OP_SIZE, OP_VERIFY,
+};
+use std::fmt;
+
+// Witness bytes for a signature: length byte plus the largest DER signature with sighash flag
+const SIGNATURE_WEIGHT: usize = 1 + 72;
+// Length byte plus the 32 byte preimage
+const PREIMAGE_WEIGHT: usize = 1 + 32;
+// Timelocks must fit in a positive 4 byte script number
+const MAX_LOCKTIME: u32 = 0x7fffffff;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Policy {
+    Key(Vec<u8>),
+    Older(u32),
+    After(u32),
+    Sha256([u8; 32]),
+    And(Box<Policy>, Box<Policy>),
+    Or(Box<Policy>, Box<Policy>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PolicyError {
+    Syntax(String),
+    InvalidKey(String),
+    InvalidHash(String),
+    InvalidLocktime(String),
+    // The script isn't one this compiler could have produced
+    UnknownScript,
+}
+
+impl fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PolicyError::Syntax(rest) => write!(f, "syntax error at '{}'", rest),
+            PolicyError::InvalidKey(key) => write!(f, "'{}' is not a compressed public key", key),
+            PolicyError::InvalidHash(hash) => write!(f, "'{}' is not a 32 byte hex hash", hash),
+            PolicyError::InvalidLocktime(value) => write!(f, "'{}' is not a valid timelock", value),
+            PolicyError::UnknownScript => write!(f, "script does not match any policy fragment"),
+        }
+    }
+}
+
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Policy::Key(key) => write!(f, "pk({})", hex::encode(key)),
+            Policy::Older(blocks) => write!(f, "older({})", blocks),
+            Policy::After(height) => write!(f, "after({})", height),
+            Policy::Sha256(hash) => write!(f, "sha256({})", hex::encode(hash)),
+            Policy::And(left, right) => write!(f, "and({},{})", left, right),
+            Policy::Or(left, right) => write!(f, "or({},{})", left, right),
+        }
+    }
+}
+
+fn parse_key(arg: &str) -> Result<Vec<u8>, PolicyError> {
+    match hex::decode(arg) {
+        Ok(key) if key.len() == 33 && matches!(key[0], 0x02 | 0x03) => Ok(key),
+        _ => Err(PolicyError::InvalidKey(arg.to_string())),
+    }
+}
+
+fn parse_locktime(arg: &str) -> Result<u32, PolicyError> {
+    match arg.parse::<u32>() {
+        Ok(value) if (1..=MAX_LOCKTIME).contains(&value) => Ok(value),
+        _ => Err(PolicyError::InvalidLocktime(arg.to_string())),
+    }
+}
+
+// Split "a,b" at the top level comma, ignoring commas inside nested parentheses
+fn split_args(args: &str) -> Result<(&str, &str), PolicyError> {
+    let mut depth = 0;
+    for (i, c) in args.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => return Ok((&args[..i], &args[i + 1..])),
+            _ => {}
+        }
+    }
+    Err(PolicyError::Syntax(args.to_string()))
+}
+
+impl Policy {
+    pub fn parse(policy: &str) -> Result<Policy, PolicyError> {
+        let policy: String = policy.chars().filter(|c| !c.is_whitespace()).collect();
+        let (name, args) = policy
+            .split_once('(')
+            .and_then(|(name, rest)| Some((name, rest.strip_suffix(')')?)))
+            .ok_or_else(|| PolicyError::Syntax(policy.clone()))?;
+        match name {
+            "pk" => Ok(Policy::Key(parse_key(args)?)),
+            "older" => Ok(Policy::Older(parse_locktime(args)?)),
+            "after" => Ok(Policy::After(parse_locktime(args)?)),
+            "sha256" => {
+                let hash = hex::decode(args).ok().and_then(|h| h.try_into().ok());
+                Ok(Policy::Sha256(hash.ok_or_else(|| PolicyError::InvalidHash(args.to_string()))?))
+            }
+            "and" | "or" => {
+                let (left, right) = split_args(args)?;
+                let (left, right) = (Box::new(Policy::parse(left)?), Box::new(Policy::parse(right)?));
+                Ok(if name == "and" { Policy::And(left, right) } else { Policy::Or(left, right) })
+            }
+            _ => Err(PolicyError::Syntax(policy.clone())),
+        }
+    }
+
+    fn build(&self, builder: ScriptBuilder, verify: bool) -> ScriptBuilder {
+        match self {
+            Policy::Key(key) => builder
+                .push_bytes(key)
+                .push_opcode(if verify { OP_CHECKSIGVERIFY } else { OP_CHECKSIG }),
+            Policy::Older(blocks) => {
+                let builder = builder.push_int(*blocks as i64).push_opcode(OP_CHECKSEQUENCEVERIFY);
+                if verify { builder.push_opcode(OP_VERIFY) } else { builder }
+            }
+            Policy::After(height) => {
+                let builder = builder.push_int(*height as i64).push_opcode(OP_CHECKLOCKTIMEVERIFY);
+                if verify { builder.push_opcode(OP_VERIFY) } else { builder }
+            }
+            // the size check stops a 0 byte "preimage" from taking the false branch of an or
+            Policy::Sha256(hash) => builder
+                .push_opcode(OP_SIZE)
+                .push_int(32)
+                .push_opcode(OP_EQUALVERIFY)
+                .push_opcode(OP_SHA256)
+                .push_bytes(hash)
+                .push_opcode(if verify { OP_EQUALVERIFY } else { OP_EQUAL }),
+            // X must succeed and leave nothing behind, then Y decides
+            Policy::And(left, right) => right.build(left.build(builder, true), verify),
+            // the witness picks a branch with a 1 or an empty push
+            Policy::Or(left, right) => {
+                let builder = right.build(left.build(builder.push_opcode(OP_IF), false).push_opcode(OP_ELSE), false);
+                let builder = builder.push_opcode(OP_ENDIF);
+                if verify { builder.push_opcode(OP_VERIFY) } else { builder }
+            }
+        }
+    }
+
+    pub fn compile(&self) -> Vec<u8> {
+        self.build(ScriptBuilder::new(), false).into_bytes()
+    }
+
+    // Nested ands come back right associated: and(and(A,B),C) decompiles to and(A,and(B,C))
+    pub fn from_script(script: &[u8]) -> Result<Policy, PolicyError> {
+        let instructions = instructions(script).map_err(|_| PolicyError::UnknownScript)?;
+        let mut parser = Decompiler { instructions: &instructions, pos: 0 };
+        let policy = parser.sequence()?;
+        if parser.pos != instructions.len() {
+            return Err(PolicyError::UnknownScript);
+        }
+        Ok(policy)
+    }
+
+    // Largest witness (in bytes, excluding the script itself) any way of satisfying the policy can need
+    pub fn max_satisfaction_weight(&self) -> usize {
+        match self {
+            Policy::Key(_) => SIGNATURE_WEIGHT,
+            Policy::Older(_) | Policy::After(_) => 0,
+            Policy::Sha256(_) => PREIMAGE_WEIGHT,
+            Policy::And(left, right) => left.max_satisfaction_weight() + right.max_satisfaction_weight(),
+            // the branch selector is a 1 byte push (2 bytes) or an empty push (1 byte)
+            Policy::Or(left, right) => (left.max_satisfaction_weight() + 2).max(right.max_satisfaction_weight() + 1),
+        }
+    }
+}
+
+struct Decompiler<'a> {
+    instructions: &'a [Instruction],
+    pos: usize,
+}
+
+impl Decompiler<'_> {
+    fn next(&mut self) -> Option<&Instruction> {
+        let instruction = self.instructions.get(self.pos);
+        self.pos += 1;
+        instruction
+    }
+
+    fn peek_op(&self) -> Option<u8> {
+        match self.instructions.get(self.pos) {
+            Some(Instruction::Op(op)) => Some(*op),
+            _ => None,
+        }
+    }
+
+    fn expect_op(&mut self, expected: u8) -> Result<(), PolicyError> {
+        match self.next() {
+            Some(Instruction::Op(op)) if *op == expected => Ok(()),
+            _ => Err(PolicyError::UnknownScript),
+        }
+    }
+
+    fn number(&mut self, max_size: usize) -> Result<i64, PolicyError> {
+        match self.next() {
+            Some(Instruction::Push(data)) => decode_script_num(data, max_size).ok_or(PolicyError::UnknownScript),
+            Some(Instruction::Op(op)) if (OP_1..=OP_16).contains(op) => Ok((op - OP_1 + 1) as i64),
+            _ => Err(PolicyError::UnknownScript),
+        }
+    }
+
+    fn locktime(&mut self) -> Result<u32, PolicyError> {
+        let value = self.number(MAX_LOCKTIME_NUM_SIZE)?;
+        u32::try_from(value).ok().filter(|v| (1..=MAX_LOCKTIME).contains(v)).ok_or(PolicyError::UnknownScript)
+    }
+
+    // Fragments up to the end of the script or branch, every one but the last is in verify form
+    fn sequence(&mut self) -> Result<Policy, PolicyError> {
+        let (policy, verified) = self.fragment()?;
+        let at_end = matches!(self.peek_op(), Some(OP_ELSE | OP_ENDIF)) || self.pos >= self.instructions.len();
+        match (verified, at_end) {
+            (false, true) => Ok(policy),
+            (true, false) => Ok(Policy::And(Box::new(policy), Box::new(self.sequence()?))),
+            _ => Err(PolicyError::UnknownScript),
+        }
+    }
+
+    // One fragment and whether it was compiled in verify form
+    fn fragment(&mut self) -> Result<(Policy, bool), PolicyError> {
+        let policy = match self.instructions.get(self.pos) {
+            Some(Instruction::Push(key)) if key.len() == 33 => {
+                let key = key.clone();
+                self.pos += 1;
+                return match self.next() {
+                    Some(Instruction::Op(OP_CHECKSIG)) => Ok((Policy::Key(key), false)),
+                    Some(Instruction::Op(OP_CHECKSIGVERIFY)) => Ok((Policy::Key(key), true)),
+                    _ => Err(PolicyError::UnknownScript),
+                };
+            }
+            Some(Instruction::Op(OP_SIZE)) => {
+                self.pos += 1;
+                if self.number(MAX_SCRIPT_NUM_SIZE)? != 32 {
+                    return Err(PolicyError::UnknownScript);
+                }
+                self.expect_op(OP_EQUALVERIFY)?;
+                self.expect_op(OP_SHA256)?;
+                let hash = match self.next() {
+                    Some(Instruction::Push(hash)) => hash.clone().try_into().map_err(|_| PolicyError::UnknownScript)?,
+                    _ => return Err(PolicyError::UnknownScript),
+                };
+                return match self.next() {
+                    Some(Instruction::Op(OP_EQUAL)) => Ok((Policy::Sha256(hash), false)),
+                    Some(Instruction::Op(OP_EQUALVERIFY)) => Ok((Policy::Sha256(hash), true)),
+                    _ => Err(PolicyError::UnknownScript),
+                };
+            }
+            Some(Instruction::Op(OP_IF)) => {
+                self.pos += 1;
+                let left = self.sequence()?;
+                self.expect_op(OP_ELSE)?;
+                let right = self.sequence()?;
+                self.expect_op(OP_ENDIF)?;
+                Policy::Or(Box::new(left), Box::new(right))
+            }
+            _ => {
+                let value = self.locktime()?;
+                match self.next() {
+                    Some(Instruction::Op(OP_CHECKSEQUENCEVERIFY)) => Policy::Older(value),
+                    Some(Instruction::Op(OP_CHECKLOCKTIMEVERIFY)) => Policy::After(value),
+                    _ => return Err(PolicyError::UnknownScript),
+                }
+            }
+        };
+        // timelocks and ors take a trailing OP_VERIFY in verify form
+        if self.peek_op() == Some(OP_VERIFY) {
+            self.pos += 1;
+            return Ok((policy, true));
+        }
+        Ok((policy, false))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::to_asm;
+
+    const KEY_A: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+    const KEY_B: &str = "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5";
+
+    #[test]
+    fn test_parse_and_display() {
+        let text = format!("and(pk({}),or(pk({}),older(144)))", KEY_A, KEY_B);
+        let policy = Policy::parse(&format!("and(pk({}), or(pk({}), older(144)))", KEY_A, KEY_B)).unwrap();
+        assert_eq!(policy.to_string(), text);
+        assert_eq!(Policy::parse("pk(02abcd)"), Err(PolicyError::InvalidKey(String::from("02abcd"))));
+        assert_eq!(Policy::parse("older(0)"), Err(PolicyError::InvalidLocktime(String::from("0"))));
+        assert!(matches!(Policy::parse("xor(older(1),older(2))"), Err(PolicyError::Syntax(_))));
+        assert!(matches!(Policy::parse("and(older(1))"), Err(PolicyError::Syntax(_))));
+    }
+
+    #[test]
+    fn test_compile() {
+        let policy = Policy::parse(&format!("and(pk({}),or(pk({}),older(144)))", KEY_A, KEY_B)).unwrap();
+        let asm = to_asm(&policy.compile()).unwrap();
+        assert_eq!(
+            asm,
+            format!("0x{} OP_CHECKSIGVERIFY OP_IF 0x{} OP_CHECKSIG OP_ELSE 0x9000 OP_CHECKSEQUENCEVERIFY OP_ENDIF", KEY_A, KEY_B)
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let hash = "11".repeat(32);
+        for text in [
+            format!("pk({})", KEY_A),
+            format!("and(pk({}),after(700000))", KEY_A),
+            format!("or(and(pk({}),sha256({})),and(pk({}),older(10)))", KEY_A, hash, KEY_B),
+            format!("and(or(pk({}),pk({})),and(older(5),pk({})))", KEY_A, KEY_B, KEY_A),
+        ] {
+            let policy = Policy::parse(&text).unwrap();
+            assert_eq!(Policy::from_script(&policy.compile()), Ok(policy));
+        }
+        assert_eq!(Policy::from_script(&[OP_CHECKSIG]), Err(PolicyError::UnknownScript));
+        // a 9 byte number used to overflow the decoder
+        let oversized = [0x09, 1, 2, 3, 4, 5, 6, 7, 8, 0x89, OP_CHECKSEQUENCEVERIFY];
+        assert_eq!(Policy::from_script(&oversized), Err(PolicyError::UnknownScript));
+    }
+
+    #[test]
+    fn test_satisfaction_weight() {
+        let policy = Policy::parse(&format!("and(pk({}),or(pk({}),older(144)))", KEY_A, KEY_B)).unwrap();
+        // A's signature plus the larger branch, B's signature with a 1 selector
+        assert_eq!(policy.max_satisfaction_weight(), SIGNATURE_WEIGHT + SIGNATURE_WEIGHT + 2);
+        let policy = Policy::parse(&format!("or(older(1),sha256({}))", "00".repeat(32))).unwrap();
+        assert_eq!(policy.max_satisfaction_weight(), PREIMAGE_WEIGHT + 1);
+    }
+}
@@ -0,0 +1,507 @@
+#![allow(unused)]
+
+/* Views over the chain shaped like the answers of the matching bitcoin core rpc commands */
+
+use crate::address::address_to_script;
+use crate::block::{Block, BlockChain, Transaction, TxOut};
+use crate::hashes::Sha256Hash;
+use crate::store::ChainStore;
+use crate::merkle::{txid_bytes, PartialMerkleTree};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScannedUtxo {
+    pub txid: String,
+    pub vout: usize,
+    pub satoshis: u64,
+    pub height: usize,
+    pub descriptor: String,
+}
+
+// Like gettxoutsetinfo, but utxo_hash is the additive rolling UtxoSetHash, not core's
+// hash_serialized or muhash. Fine for comparing nodes, not a collision resistant commitment.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UtxoSetInfo {
+    pub height: usize,
+    pub best_block: Option<String>,
+    pub txouts: usize,
+    pub total_amount: u128,
+    pub utxo_hash: Sha256Hash,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScanError {
+    // Only addr(...) descriptors can be matched until outputs have scripts
+    UnsupportedDescriptor(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SubmitBlockResult {
+    Accepted,
+    Duplicate,
+    // Builds on a block we have but not on our tip, so it wasn't connected. Only the
+    // rules that don't need the branch's coins were checked (sigops, timestamp, output values).
+    Inconclusive,
+    BadPrevBlock,
+    Invalid(String),
+}
+
+impl SubmitBlockResult {
+    // What the submitblock rpc returns: null when accepted, otherwise a reason string
+    pub fn reason(&self) -> Option<&str> {
+        match self {
+            SubmitBlockResult::Accepted => None,
+            SubmitBlockResult::Duplicate => Some("duplicate"),
+            SubmitBlockResult::Inconclusive => Some("inconclusive"),
+            SubmitBlockResult::BadPrevBlock => Some("bad-prevblk"),
+            SubmitBlockResult::Invalid(reason) => Some(reason),
+        }
+    }
+}
+
+// gettxoutproof result: the block it refers to plus the partial merkle tree
+#[derive(Clone, Debug, PartialEq)]
+pub struct MerkleBlock {
+    pub block_hash: String,
+    pub merkle_root: String,
+    pub tree: PartialMerkleTree,
+}
+
+impl MerkleBlock {
+    // Check the proof against a merkle root the caller already trusts (e.g. from
+    // a header it validated) and return the txids it proves
+    pub fn verify(&self, trusted_merkle_root: &str) -> Option<Vec<String>> {
+        let (root, matched) = self.tree.extract_matches()?;
+        if hex::encode(root) != trusted_merkle_root || self.merkle_root != trusted_merkle_root {
+            return None;
+        }
+        Some(matched.iter().map(hex::encode).collect())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockHeaderInfo {
+    pub hash: String,
+    pub height: usize,
+    pub confirmations: usize,
+    pub prev_hash: String,
+    pub timestamp: u64,
+    pub nonce: u64,
+    pub tx_count: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct InputInfo {
+    pub prev_txid: String,
+    pub out: u32,
+    pub sequence: u32,
+    pub prevout: Option<TxOutInfo>, // None if the spent output isn't in the chain
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TxOutInfo {
+    pub public_address: String,
+    pub satoshis: u64,
+    pub script_pub_key: Option<String>, // hex, None if the address doesn't decode
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TxInfo {
+    pub txid: String,
+    pub inputs: Vec<InputInfo>,
+    pub outputs: Vec<TxOutInfo>,
+    pub fee: Option<u64>, // None for coinbase or when a prevout can't be resolved
+    pub sigops: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BlockInfo {
+    // verbosity 0
+    Raw(String),
+    // verbosity 1
+    Summary { header: BlockHeaderInfo, txids: Vec<String> },
+    // verbosity 2
+    Full { header: BlockHeaderInfo, transactions: Vec<TxInfo> },
+}
+
+impl From<&TxOut> for TxOutInfo {
+    fn from(txout: &TxOut) -> Self {
+        TxOutInfo {
+            public_address: txout.public_address.clone(),
+            satoshis: txout.satoshis,
+            script_pub_key: address_to_script(&txout.public_address).ok().map(hex::encode),
+        }
+    }
+}
+
+impl<S: ChainStore> BlockChain<S> {
+    // getblock: 0 = raw hex, 1 = header and txids, 2 (or more) = header and decoded transactions
+    pub fn get_block(&self, hash: &str, verbosity: u8) -> Option<BlockInfo> {
+        let block = self.get_block_by_hash(hash)?;
+        if verbosity == 0 {
+            return Some(BlockInfo::Raw(hex::encode(block.serialize())));
+        }
+        let header = self.header_info(block)?;
+        if verbosity == 1 {
+            let txids = block.transactions.iter().map(|tx| tx.txid.clone()).collect();
+            return Some(BlockInfo::Summary { header, txids });
+        }
+        let transactions = block.transactions.iter().map(|tx| self.tx_info(tx)).collect();
+        Some(BlockInfo::Full { header, transactions })
+    }
+
+    // getrawtransaction: hex of a confirmed transaction. Without the txindex the
+    // block it's in has to be given, like in bitcoin core.
+    pub fn get_raw_transaction(&self, txid: &str, block_hash: Option<&str>) -> Option<String> {
+        let tx = match block_hash {
+            Some(hash) => self.get_block_by_hash(hash)?.get_transaction(txid)?,
+            None if self.has_txindex() => self.get_transaction(txid)?,
+            None => return None,
+        };
+        Some(hex::encode(tx.serialize()))
+    }
+
+    // Cheap to call every block, nothing is rehashed
+    pub fn utxo_set_info(&self) -> UtxoSetInfo {
+        UtxoSetInfo {
+            height: self.get_block_count().saturating_sub(1),
+            best_block: self.get_best_block_hash().map(String::from),
+            txouts: self.get_utxo_count(),
+            total_amount: self.utxo_stats().total_value,
+            utxo_hash: self.utxo_set_hash().digest(),
+        }
+    }
+
+    // scantxoutset: find the unspent outputs paying to any of the descriptors
+    pub fn scan_utxos(&self, descriptors: &[&str]) -> Result<Vec<ScannedUtxo>, ScanError> {
+        let mut wanted = std::collections::HashMap::new();
+        for descriptor in descriptors {
+            let address = descriptor
+                .trim()
+                .strip_prefix("addr(")
+                .and_then(|rest| rest.strip_suffix(')'))
+                .ok_or_else(|| ScanError::UnsupportedDescriptor(descriptor.to_string()))?;
+            wanted.insert(address, *descriptor);
+        }
+
+        let mut found: Vec<ScannedUtxo> = self
+            .utxos()
+            .filter_map(|(txid, vout, coin)| {
+                let descriptor = wanted.get(coin.txout.public_address.as_str())?;
+                Some(ScannedUtxo {
+                    txid: txid.to_string(),
+                    vout,
+                    satoshis: coin.txout.satoshis,
+                    height: coin.height,
+                    descriptor: descriptor.to_string(),
+                })
+            })
+            .collect();
+        found.sort_by(|a, b| (a.height, &a.txid, a.vout).cmp(&(b.height, &b.txid, b.vout)));
+        Ok(found)
+    }
+
+    // submitblock: fully check the block and connect it if it extends the tip
+    pub fn submit_block(&mut self, block: Block) -> SubmitBlockResult {
+        if block.hash != block.calculate_hash() {
+            return SubmitBlockResult::Invalid(String::from("bad-blk-hash"));
+        }
+        if self.get_block_by_hash(&block.hash).is_some() {
+            return SubmitBlockResult::Duplicate;
+        }
+        match self.get_best_block_hash() {
+            None if block.height != 0 => return SubmitBlockResult::BadPrevBlock,
+            None => {}
+            Some(tip) if tip != block.prev_hash => {
+                if self.get_block_by_hash(&block.prev_hash).is_none() {
+                    return SubmitBlockResult::BadPrevBlock;
+                }
+                return match self.check_block_context_free(&block) {
+                    Ok(()) => SubmitBlockResult::Inconclusive,
+                    Err(reason) => SubmitBlockResult::Invalid(String::from(reason)),
+                };
+            }
+            Some(_) if block.height as usize != self.get_block_count() => {
+                return SubmitBlockResult::Invalid(String::from("bad-height"));
+            }
+            Some(_) => {}
+        }
+        if let Err(reason) = self.check_block(&block) {
+            return SubmitBlockResult::Invalid(String::from(reason));
+        }
+        // already checked, add_block would run every rule again
+        self.connect_block(block);
+        SubmitBlockResult::Accepted
+    }
+
+    pub fn submit_block_hex(&mut self, block_hex: &str) -> SubmitBlockResult {
+        match hex::decode(block_hex).ok().and_then(|bytes| Block::deserialize(&bytes).ok()) {
+            Some(block) => self.submit_block(block),
+            None => SubmitBlockResult::Invalid(String::from("block-decode-failed")),
+        }
+    }
+
+    // gettxoutproof: prove the txids are in the block (looked up through the tx index if not given)
+    pub fn get_tx_out_proof(&self, txids: &[&str], block_hash: Option<&str>) -> Option<MerkleBlock> {
+        let first = txids.first()?;
+        let block_hash = match block_hash {
+            Some(hash) => hash,
+            None => self.get_tx_location(first)?.0,
+        };
+        let block = self.get_block_by_hash(block_hash)?;
+        if txids.iter().any(|txid| block.get_transaction(txid).is_none()) {
+            return None;
+        }
+        let leaves: Vec<[u8; 32]> = block.transactions.iter().map(|tx| txid_bytes(&tx.txid)).collect();
+        let matches: Vec<bool> = block.transactions.iter().map(|tx| txids.contains(&tx.txid.as_str())).collect();
+        Some(MerkleBlock {
+            block_hash: block.hash.clone(),
+            merkle_root: block.merkle_root(),
+            tree: PartialMerkleTree::new(&leaves, &matches),
+        })
+    }
+
+    // verifytxoutproof: the proof's block must be in our chain
+    pub fn verify_tx_out_proof(&self, proof: &MerkleBlock) -> Option<Vec<String>> {
+        let block = self.get_block_by_hash(&proof.block_hash)?;
+        proof.verify(&block.merkle_root())
+    }
+
+    fn header_info(&self, block: &Block) -> Option<BlockHeaderInfo> {
+        let height = self.get_block_height(&block.hash)?;
+        Some(BlockHeaderInfo {
+            hash: block.hash.clone(),
+            height,
+            confirmations: self.get_block_count() - height,
+            prev_hash: block.prev_hash.clone(),
+            timestamp: block.timestamp,
+            nonce: block.nonce,
+            tx_count: block.transactions.len(),
+        })
+    }
+
+    pub fn tx_info(&self, tx: &Transaction) -> TxInfo {
+        let inputs: Vec<InputInfo> = tx
+            .inputs
+            .iter()
+            .map(|txin| InputInfo {
+                prev_txid: txin.prev_txid.clone(),
+                out: txin.out,
+                sequence: txin.sequence,
+                prevout: self
+                    .get_transaction(&txin.prev_txid)
+                    .and_then(|prev| prev.outputs.iter().nth(txin.out as usize))
+                    .map(TxOutInfo::from),
+            })
+            .collect();
+        let outputs: Vec<TxOutInfo> = tx.outputs.iter().map(TxOutInfo::from).collect();
+
+        let fee = if inputs.is_empty() {
+            None
+        } else {
+            // checked sums, a fee that overflows isn't a fee
+            let spent = inputs.iter().try_fold(0u64, |acc, i| acc.checked_add(i.prevout.as_ref()?.satoshis));
+            let created = outputs.iter().try_fold(0u64, |acc, o| acc.checked_add(o.satoshis));
+            spent.zip(created).and_then(|(spent, created)| spent.checked_sub(created))
+        };
+
+        TxInfo {
+            txid: tx.txid.clone(),
+            inputs,
+            outputs,
+            fee,
+            sigops: tx.sigop_count(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::{TxIn, TxOut, MAX_MONEY};
+    use std::collections::LinkedList as List;
+
+    fn chain() -> (BlockChain, Block, Block) {
+        let mut blockchain = BlockChain::new();
+        let mut genesis = Block::new(String::new());
+        genesis.add_transaction(Transaction::new(List::new(), vec![TxOut::new(String::from("miner"), 50)].into_iter().collect()));
+        let coinbase = genesis.transactions.front().unwrap().txid.clone();
+
+        let mut block1 = Block::new(genesis.hash.clone());
+        block1.height = 1;
+        let txin = TxIn::new(coinbase, 0, String::from("signature"));
+        block1.add_transaction(Transaction::new(vec![txin].into_iter().collect(), vec![TxOut::new(String::from("bob"), 45)].into_iter().collect()));
+
+        blockchain.add_block(genesis.clone());
+        blockchain.add_block(block1.clone());
+        (blockchain, genesis, block1)
+    }
+
+    #[test]
+    fn test_get_block_raw() {
+        let (blockchain, genesis, _) = chain();
+        match blockchain.get_block(&genesis.hash, 0) {
+            Some(BlockInfo::Raw(raw)) => assert_eq!(raw, hex::encode(genesis.serialize())),
+            _ => panic!("expected raw block"),
+        }
+    }
+
+    #[test]
+    fn test_get_block_summary() {
+        let (blockchain, genesis, _) = chain();
+        match blockchain.get_block(&genesis.hash, 1) {
+            Some(BlockInfo::Summary { header, txids }) => {
+                assert_eq!(header.height, 0);
+                assert_eq!(header.confirmations, 2);
+                assert_eq!(txids.len(), 1);
+            }
+            _ => panic!("expected block summary"),
+        }
+    }
+
+    #[test]
+    fn test_get_block_full() {
+        let (blockchain, _, block1) = chain();
+        match blockchain.get_block(&block1.hash, 2) {
+            Some(BlockInfo::Full { header, transactions }) => {
+                assert_eq!(header.confirmations, 1);
+                let tx = &transactions[0];
+                assert_eq!(tx.inputs[0].prevout.as_ref().unwrap().satoshis, 50);
+                assert_eq!(tx.fee, Some(5));
+            }
+            _ => panic!("expected full block"),
+        }
+        assert!(blockchain.get_block("unknown", 2).is_none());
+    }
+
+    #[test]
+    fn test_submit_block() {
+        let (mut blockchain, genesis, block1) = chain();
+        assert_eq!(blockchain.submit_block(block1.clone()), SubmitBlockResult::Duplicate);
+
+        let mut block2 = Block::new(block1.hash.clone());
+        block2.height = 2;
+        block2.hash = block2.calculate_hash();
+        let block2_hex = hex::encode(block2.serialize());
+
+        let mut tampered = block2.clone();
+        tampered.nonce += 1;
+        assert_eq!(blockchain.submit_block(tampered).reason(), Some("bad-blk-hash"));
+
+        let mut orphan = Block::new(String::from("unknown"));
+        orphan.height = 2;
+        orphan.hash = orphan.calculate_hash();
+        assert_eq!(blockchain.submit_block(orphan), SubmitBlockResult::BadPrevBlock);
+
+        let mut fork = Block::new(genesis.hash.clone());
+        fork.height = 1;
+        fork.nonce = 1;
+        fork.hash = fork.calculate_hash();
+        assert_eq!(blockchain.submit_block(fork.clone()), SubmitBlockResult::Inconclusive);
+        // a side branch is still held to the rules that don't need its coins
+        fork.add_transaction(Transaction::new(List::new(), List::from([TxOut::new(String::from("miner"), MAX_MONEY + 1)])));
+        fork.hash = fork.calculate_hash();
+        assert_eq!(blockchain.submit_block(fork.clone()).reason(), Some("bad-txns-vout-toolarge"));
+        fork.transactions.clear();
+        fork.timestamp = u64::MAX / 2;
+        fork.hash = fork.calculate_hash();
+        assert_eq!(blockchain.submit_block(fork).reason(), Some("time-too-new"));
+
+        assert_eq!(blockchain.submit_block_hex("zz").reason(), Some("block-decode-failed"));
+        assert_eq!(blockchain.submit_block_hex(&block2_hex), SubmitBlockResult::Accepted);
+        assert_eq!(blockchain.get_best_block_hash(), Some(block2.hash.as_str()));
+    }
+
+    #[test]
+    fn test_submit_block_checks_once() {
+        use crate::store::ChainStore;
+        use crate::validation::Rule;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        struct Counted(Arc<AtomicUsize>);
+        impl<S: ChainStore> Rule<S> for Counted {
+            fn name(&self) -> &'static str {
+                "counted"
+            }
+
+            fn check(&self, _chain: &BlockChain<S>, _block: &Block) -> Result<(), &'static str> {
+                self.0.fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            }
+        }
+
+        let (mut blockchain, _, block1) = chain();
+        let runs = Arc::new(AtomicUsize::new(0));
+        blockchain.rules_mut().push(Counted(runs.clone()));
+        let mut block2 = Block::new(block1.hash.clone());
+        block2.height = 2;
+        block2.hash = block2.calculate_hash();
+        assert_eq!(blockchain.submit_block(block2), SubmitBlockResult::Accepted);
+        assert_eq!(runs.load(Ordering::Relaxed), 1);
+        assert_eq!(blockchain.get_block_count(), 3);
+    }
+
+    #[test]
+    fn test_tx_out_proof() {
+        let (blockchain, genesis, block1) = chain();
+        let txid = block1.transactions.front().unwrap().txid.clone();
+        let proof = blockchain.get_tx_out_proof(&[&txid], None).unwrap();
+        assert_eq!(proof.block_hash, block1.hash);
+        assert_eq!(proof.verify(&block1.merkle_root()), Some(vec![txid.clone()]));
+        assert_eq!(blockchain.verify_tx_out_proof(&proof), Some(vec![txid.clone()]));
+
+        // wrong root, wrong block, unknown tx
+        assert!(proof.verify(&genesis.merkle_root()).is_none());
+        assert!(blockchain.get_tx_out_proof(&[&txid], Some(&genesis.hash)).is_none());
+        assert!(blockchain.get_tx_out_proof(&["unknown"], None).is_none());
+    }
+
+    #[test]
+    fn test_scan_utxos() {
+        let (blockchain, _, block1) = chain();
+        let found = blockchain.scan_utxos(&["addr(bob)", "addr(miner)"]).unwrap();
+        // the miner's coin was spent in block 1
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].txid, block1.transactions.front().unwrap().txid);
+        assert_eq!(found[0].satoshis, 45);
+        assert_eq!(found[0].height, 1);
+        assert_eq!(found[0].descriptor, "addr(bob)");
+
+        assert_eq!(
+            blockchain.scan_utxos(&["pkh(02aa)"]),
+            Err(ScanError::UnsupportedDescriptor(String::from("pkh(02aa)")))
+        );
+    }
+
+    #[test]
+    fn test_get_raw_transaction() {
+        let (mut blockchain, genesis, block1) = chain();
+        let coinbase = genesis.transactions.front().unwrap();
+        let raw = hex::encode(coinbase.serialize());
+        assert_eq!(blockchain.get_raw_transaction(&coinbase.txid, None), Some(raw.clone()));
+
+        blockchain.set_txindex(false);
+        assert_eq!(blockchain.get_raw_transaction(&coinbase.txid, None), None);
+        assert_eq!(blockchain.get_raw_transaction(&coinbase.txid, Some(&genesis.hash)), Some(raw));
+        assert_eq!(blockchain.get_raw_transaction(&coinbase.txid, Some(&block1.hash)), None);
+    }
+
+    #[test]
+    fn test_utxo_set_info() {
+        let (mut blockchain, genesis, block1) = chain();
+        let info = blockchain.utxo_set_info();
+        assert_eq!(info.height, 1);
+        assert_eq!(info.best_block.as_deref(), Some(block1.hash.as_str()));
+        assert_eq!((info.txouts, info.total_amount), (1, 45));
+
+        // a second node that saw the same blocks agrees, and undoing a block undoes its hash change
+        let mut other = BlockChain::new();
+        other.add_block(genesis.clone());
+        let after_genesis = other.utxo_set_info().utxo_hash;
+        other.add_block(block1);
+        assert_eq!(other.utxo_set_info().utxo_hash, info.utxo_hash);
+        blockchain.disconnect_tip();
+        assert_eq!(blockchain.utxo_set_info().utxo_hash, after_genesis);
+        assert_ne!(after_genesis, info.utxo_hash);
+    }
+}
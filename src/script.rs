@@ -0,0 +1,508 @@
+#![allow(unused)]
+
+/* Building, parsing and printing scripts */
+
+use std::fmt;
+
+pub const OP_0: u8 = 0x00;
+pub const OP_PUSHDATA1: u8 = 0x4c;
+pub const OP_PUSHDATA2: u8 = 0x4d;
+pub const OP_PUSHDATA4: u8 = 0x4e;
+pub const OP_1NEGATE: u8 = 0x4f;
+pub const OP_1: u8 = 0x51;
+pub const OP_16: u8 = 0x60;
+pub const OP_NOP: u8 = 0x61;
+pub const OP_IF: u8 = 0x63;
+pub const OP_NOTIF: u8 = 0x64;
+pub const OP_ELSE: u8 = 0x67;
+pub const OP_ENDIF: u8 = 0x68;
+pub const OP_VERIFY: u8 = 0x69;
+pub const OP_RETURN: u8 = 0x6a;
+pub const OP_TOALTSTACK: u8 = 0x6b;
+pub const OP_FROMALTSTACK: u8 = 0x6c;
+pub const OP_2DROP: u8 = 0x6d;
+pub const OP_2DUP: u8 = 0x6e;
+pub const OP_IFDUP: u8 = 0x73;
+pub const OP_DEPTH: u8 = 0x74;
+pub const OP_DROP: u8 = 0x75;
+pub const OP_DUP: u8 = 0x76;
+pub const OP_NIP: u8 = 0x77;
+pub const OP_OVER: u8 = 0x78;
+pub const OP_SWAP: u8 = 0x7c;
+pub const OP_SIZE: u8 = 0x82;
+pub const OP_EQUAL: u8 = 0x87;
+pub const OP_EQUALVERIFY: u8 = 0x88;
+pub const OP_1ADD: u8 = 0x8b;
+pub const OP_1SUB: u8 = 0x8c;
+pub const OP_NOT: u8 = 0x91;
+pub const OP_0NOTEQUAL: u8 = 0x92;
+pub const OP_ADD: u8 = 0x93;
+pub const OP_SUB: u8 = 0x94;
+pub const OP_BOOLAND: u8 = 0x9a;
+pub const OP_BOOLOR: u8 = 0x9b;
+pub const OP_NUMEQUAL: u8 = 0x9c;
+pub const OP_NUMEQUALVERIFY: u8 = 0x9d;
+pub const OP_LESSTHAN: u8 = 0x9f;
+pub const OP_GREATERTHAN: u8 = 0xa0;
+pub const OP_MIN: u8 = 0xa3;
+pub const OP_MAX: u8 = 0xa4;
+pub const OP_WITHIN: u8 = 0xa5;
+pub const OP_RIPEMD160: u8 = 0xa6;
+pub const OP_SHA1: u8 = 0xa7;
+pub const OP_SHA256: u8 = 0xa8;
+pub const OP_HASH160: u8 = 0xa9;
+pub const OP_HASH256: u8 = 0xaa;
+pub const OP_CODESEPARATOR: u8 = 0xab;
+pub const OP_CHECKSIG: u8 = 0xac;
+pub const OP_CHECKSIGVERIFY: u8 = 0xad;
+pub const OP_CHECKMULTISIG: u8 = 0xae;
+pub const OP_CHECKMULTISIGVERIFY: u8 = 0xaf;
+pub const OP_CHECKLOCKTIMEVERIFY: u8 = 0xb1;
+pub const OP_CHECKSEQUENCEVERIFY: u8 = 0xb2;
+pub const OP_CHECKSIGADD: u8 = 0xba;
+
+const OPCODE_NAMES: &[(u8, &str)] = &[
+    (OP_0, "OP_0"),
+    (OP_PUSHDATA1, "OP_PUSHDATA1"),
+    (OP_PUSHDATA2, "OP_PUSHDATA2"),
+    (OP_PUSHDATA4, "OP_PUSHDATA4"),
+    (OP_1NEGATE, "OP_1NEGATE"),
+    (OP_NOP, "OP_NOP"),
+    (OP_IF, "OP_IF"),
+    (OP_NOTIF, "OP_NOTIF"),
+    (OP_ELSE, "OP_ELSE"),
+    (OP_ENDIF, "OP_ENDIF"),
+    (OP_VERIFY, "OP_VERIFY"),
+    (OP_RETURN, "OP_RETURN"),
+    (OP_TOALTSTACK, "OP_TOALTSTACK"),
+    (OP_FROMALTSTACK, "OP_FROMALTSTACK"),
+    (OP_2DROP, "OP_2DROP"),
+    (OP_2DUP, "OP_2DUP"),
+    (OP_IFDUP, "OP_IFDUP"),
+    (OP_DEPTH, "OP_DEPTH"),
+    (OP_DROP, "OP_DROP"),
+    (OP_DUP, "OP_DUP"),
+    (OP_NIP, "OP_NIP"),
+    (OP_OVER, "OP_OVER"),
+    (OP_SWAP, "OP_SWAP"),
+    (OP_SIZE, "OP_SIZE"),
+    (OP_EQUAL, "OP_EQUAL"),
+    (OP_EQUALVERIFY, "OP_EQUALVERIFY"),
+    (OP_1ADD, "OP_1ADD"),
+    (OP_1SUB, "OP_1SUB"),
+    (OP_NOT, "OP_NOT"),
+    (OP_0NOTEQUAL, "OP_0NOTEQUAL"),
+    (OP_ADD, "OP_ADD"),
+    (OP_SUB, "OP_SUB"),
+    (OP_BOOLAND, "OP_BOOLAND"),
+    (OP_BOOLOR, "OP_BOOLOR"),
+    (OP_NUMEQUAL, "OP_NUMEQUAL"),
+    (OP_NUMEQUALVERIFY, "OP_NUMEQUALVERIFY"),
+    (OP_LESSTHAN, "OP_LESSTHAN"),
+    (OP_GREATERTHAN, "OP_GREATERTHAN"),
+    (OP_MIN, "OP_MIN"),
+    (OP_MAX, "OP_MAX"),
+    (OP_WITHIN, "OP_WITHIN"),
+    (OP_RIPEMD160, "OP_RIPEMD160"),
+    (OP_SHA1, "OP_SHA1"),
+    (OP_SHA256, "OP_SHA256"),
+    (OP_HASH160, "OP_HASH160"),
+    (OP_HASH256, "OP_HASH256"),
+    (OP_CODESEPARATOR, "OP_CODESEPARATOR"),
+    (OP_CHECKSIG, "OP_CHECKSIG"),
+    (OP_CHECKSIGVERIFY, "OP_CHECKSIGVERIFY"),
+    (OP_CHECKMULTISIG, "OP_CHECKMULTISIG"),
+    (OP_CHECKMULTISIGVERIFY, "OP_CHECKMULTISIGVERIFY"),
+    (OP_CHECKLOCKTIMEVERIFY, "OP_CHECKLOCKTIMEVERIFY"),
+    (OP_CHECKSEQUENCEVERIFY, "OP_CHECKSEQUENCEVERIFY"),
+    (OP_CHECKSIGADD, "OP_CHECKSIGADD"),
+];
+
+pub fn opcode_name(opcode: u8) -> Option<String> {
+    if (OP_1..=OP_16).contains(&opcode) {
+        return Some(format!("OP_{}", opcode - OP_1 + 1));
+    }
+    OPCODE_NAMES.iter().find(|(op, _)| *op == opcode).map(|(_, name)| name.to_string())
+}
+
+// Accepts names with or without the OP_ prefix, and the CLTV/CSV aliases
+pub fn opcode_by_name(name: &str) -> Option<u8> {
+    let upper = name.to_ascii_uppercase();
+    let bare = upper.strip_prefix("OP_").unwrap_or(&upper);
+    match bare {
+        "FALSE" => return Some(OP_0),
+        "TRUE" => return Some(OP_1),
+        "CLTV" => return Some(OP_CHECKLOCKTIMEVERIFY),
+        "CSV" => return Some(OP_CHECKSEQUENCEVERIFY),
+        _ => {}
+    }
+    // OP_0 to OP_16, but not bare numbers, those are pushes in asm
+    if upper.starts_with("OP_") {
+        match bare.parse::<u8>() {
+            Ok(0) => return Some(OP_0),
+            Ok(n @ 1..=16) => return Some(OP_1 + n - 1),
+            _ => {}
+        }
+    }
+    OPCODE_NAMES
+        .iter()
+        .find(|(_, known)| known.strip_prefix("OP_") == Some(bare))
+        .map(|(op, _)| *op)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScriptError {
+    // A push claims more bytes than the script has left
+    TruncatedPush { position: usize },
+    UnknownOpcode(String),
+    InvalidHex(String),
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScriptError::TruncatedPush { position } => write!(f, "push at byte {} runs past the end of the script", position),
+            ScriptError::UnknownOpcode(token) => write!(f, "unknown opcode '{}'", token),
+            ScriptError::InvalidHex(token) => write!(f, "invalid hex push '{}'", token),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Instruction {
+    Push(Vec<u8>),
+    Op(u8),
+}
+
+// The instruction starting at pos and the position after it
+fn read_instruction(script: &[u8], pos: usize) -> Result<(Instruction, usize), ScriptError> {
+    let opcode = script[pos];
+    let mut next = pos + 1;
+    let len = match opcode {
+        1..=0x4b => opcode as usize,
+        OP_PUSHDATA1 | OP_PUSHDATA2 | OP_PUSHDATA4 => {
+            let size = match opcode {
+                OP_PUSHDATA1 => 1,
+                OP_PUSHDATA2 => 2,
+                _ => 4,
+            };
+            let bytes = script.get(next..next + size).ok_or(ScriptError::TruncatedPush { position: pos })?;
+            next += size;
+            bytes.iter().rev().fold(0usize, |acc, b| (acc << 8) | *b as usize)
+        }
+        _ => return Ok((Instruction::Op(opcode), next)),
+    };
+    let data = script.get(next..next + len).ok_or(ScriptError::TruncatedPush { position: pos })?;
+    Ok((Instruction::Push(data.to_vec()), next + len))
+}
+
+// Split a script into pushes and opcodes
+pub fn instructions(script: &[u8]) -> Result<Vec<Instruction>, ScriptError> {
+    let mut result = Vec::new();
+    let mut pos = 0;
+    while pos < script.len() {
+        let (instruction, next) = read_instruction(script, pos)?;
+        result.push(instruction);
+        pos = next;
+    }
+    Ok(result)
+}
+
+// What a bare CHECKMULTISIG counts for when the key count isn't known
+pub const MAX_PUBKEYS_PER_MULTISIG: usize = 20;
+
+// Signature operations in a script. With accurate set a multisig preceded by OP_n
+// counts n, otherwise it always counts the maximum like the legacy rule.
+// A malformed script counts what was seen before the bad push.
+pub fn count_sigops(script: &[u8], accurate: bool) -> usize {
+    let mut count = 0;
+    let mut last_op = None;
+    let mut pos = 0;
+    while pos < script.len() {
+        let Ok((instruction, next)) = read_instruction(script, pos) else {
+            break;
+        };
+        pos = next;
+        let Instruction::Op(opcode) = instruction else {
+            last_op = None;
+            continue;
+        };
+        match opcode {
+            OP_CHECKSIG | OP_CHECKSIGVERIFY => count += 1,
+            OP_CHECKMULTISIG | OP_CHECKMULTISIGVERIFY => match last_op {
+                Some(op @ OP_1..=OP_16) if accurate => count += (op - OP_1 + 1) as usize,
+                _ => count += MAX_PUBKEYS_PER_MULTISIG,
+            },
+            _ => {}
+        }
+        last_op = Some(opcode);
+    }
+    count
+}
+
+// Minimal little endian sign-magnitude encoding used for numbers on the stack
+pub fn encode_script_num(n: i64) -> Vec<u8> {
+    if n == 0 {
+        return Vec::new();
+    }
+    let negative = n < 0;
+    let mut abs = n.unsigned_abs();
+    let mut result = Vec::new();
+    while abs > 0 {
+        result.push((abs & 0xff) as u8);
+        abs >>= 8;
+    }
+    // the top bit is the sign, add a byte if the magnitude already uses it
+    if result.last().unwrap() & 0x80 != 0 {
+        result.push(if negative { 0x80 } else { 0 });
+    } else if negative {
+        *result.last_mut().unwrap() |= 0x80;
+    }
+    result
+}
+
+// Longest number arithmetic opcodes accept, CLTV and CSV allow one more byte
+pub const MAX_SCRIPT_NUM_SIZE: usize = 4;
+pub const MAX_LOCKTIME_NUM_SIZE: usize = 5;
+
+// None for numbers longer than max_size, like CScriptNum's overflow error
+pub fn decode_script_num(bytes: &[u8], max_size: usize) -> Option<i64> {
+    if bytes.len() > max_size.min(8) {
+        return None;
+    }
+    let Some(last) = bytes.last() else {
+        return Some(0);
+    };
+    let mut result = bytes.iter().rev().fold(0i64, |acc, b| (acc << 8) | *b as i64);
+    // clear the sign bit and apply it
+    let sign_bit = 0x80i64 << (8 * (bytes.len() - 1));
+    if last & 0x80 != 0 {
+        result = -(result & !sign_bit);
+    }
+    Some(result)
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ScriptBuilder {
+    bytes: Vec<u8>,
+}
+
+impl ScriptBuilder {
+    pub fn new() -> Self {
+        ScriptBuilder::default()
+    }
+
+    pub fn push_opcode(mut self, opcode: u8) -> Self {
+        self.bytes.push(opcode);
+        self
+    }
+
+    // Uses the shortest push opcode for the length. Unlike the minimal push rule it
+    // doesn't turn a single byte 1..16 into OP_N, use push_int for that.
+    pub fn push_bytes(mut self, data: &[u8]) -> Self {
+        match data.len() {
+            len @ 0..=0x4b => self.bytes.push(len as u8),
+            len @ 0x4c..=0xff => self.bytes.extend([OP_PUSHDATA1, len as u8]),
+            len @ 0x100..=0xffff => {
+                self.bytes.push(OP_PUSHDATA2);
+                self.bytes.extend((len as u16).to_le_bytes());
+            }
+            len => {
+                self.bytes.push(OP_PUSHDATA4);
+                self.bytes.extend((len as u32).to_le_bytes());
+            }
+        }
+        self.bytes.extend_from_slice(data);
+        self
+    }
+
+    // Small numbers get their own opcodes, everything else is a script number push
+    pub fn push_int(self, n: i64) -> Self {
+        match n {
+            0 => self.push_opcode(OP_0),
+            -1 => self.push_opcode(OP_1NEGATE),
+            1..=16 => self.push_opcode(OP_1 + n as u8 - 1),
+            _ => self.push_bytes(&encode_script_num(n)),
+        }
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    pub fn p2pkh(pubkey_hash: &[u8; 20]) -> Vec<u8> {
+        ScriptBuilder::new()
+            .push_opcode(OP_DUP)
+            .push_opcode(OP_HASH160)
+            .push_bytes(pubkey_hash)
+            .push_opcode(OP_EQUALVERIFY)
+            .push_opcode(OP_CHECKSIG)
+            .into_bytes()
+    }
+
+    pub fn p2sh(script_hash: &[u8; 20]) -> Vec<u8> {
+        ScriptBuilder::new()
+            .push_opcode(OP_HASH160)
+            .push_bytes(script_hash)
+            .push_opcode(OP_EQUAL)
+            .into_bytes()
+    }
+
+    // OP_n <program>, covers p2wpkh, p2wsh, p2tr and future versions
+    pub fn witness_program(version: u8, program: &[u8]) -> Vec<u8> {
+        ScriptBuilder::new().push_int(version as i64).push_bytes(program).into_bytes()
+    }
+
+    pub fn p2wpkh(pubkey_hash: &[u8; 20]) -> Vec<u8> {
+        ScriptBuilder::witness_program(0, pubkey_hash)
+    }
+
+    pub fn p2wsh(script_hash: &[u8; 32]) -> Vec<u8> {
+        ScriptBuilder::witness_program(0, script_hash)
+    }
+
+    pub fn p2tr(output_key: &[u8; 32]) -> Vec<u8> {
+        ScriptBuilder::witness_program(1, output_key)
+    }
+
+    // OP_m <key>... OP_n OP_CHECKMULTISIG
+    pub fn multisig(required: usize, pubkeys: &[Vec<u8>]) -> Vec<u8> {
+        let mut builder = ScriptBuilder::new().push_int(required as i64);
+        for key in pubkeys {
+            builder = builder.push_bytes(key);
+        }
+        builder.push_int(pubkeys.len() as i64).push_opcode(OP_CHECKMULTISIG).into_bytes()
+    }
+
+    // Hash time locked contract: the receiver can spend with the preimage of
+    // payment_hash, or the sender can take the coins back after timeout blocks.
+    pub fn htlc(receiver_pubkey: &[u8], sender_pubkey: &[u8], payment_hash: &[u8; 32], timeout: i64) -> Vec<u8> {
+        ScriptBuilder::new()
+            .push_opcode(OP_IF)
+            .push_opcode(OP_SHA256)
+            .push_bytes(payment_hash)
+            .push_opcode(OP_EQUALVERIFY)
+            .push_bytes(receiver_pubkey)
+            .push_opcode(OP_ELSE)
+            .push_int(timeout)
+            .push_opcode(OP_CHECKSEQUENCEVERIFY)
+            .push_opcode(OP_DROP)
+            .push_bytes(sender_pubkey)
+            .push_opcode(OP_ENDIF)
+            .push_opcode(OP_CHECKSIG)
+            .into_bytes()
+    }
+}
+
+/*
+ asm format, tokens separated by whitespace:
+ - opcodes by name, with or without OP_ (OP_DUP, DUP, OP_1, CSV)
+ - decimal numbers are pushed like push_int (0, -1, 144)
+ - 0x followed by hex is a data push (0x89abcdef)
+*/
+pub fn parse_asm(asm: &str) -> Result<Vec<u8>, ScriptError> {
+    let mut builder = ScriptBuilder::new();
+    for token in asm.split_whitespace() {
+        builder = if let Some(data) = token.strip_prefix("0x") {
+            let bytes = hex::decode(data).map_err(|_| ScriptError::InvalidHex(token.to_string()))?;
+            builder.push_bytes(&bytes)
+        } else if let Ok(n) = token.parse::<i64>() {
+            builder.push_int(n)
+        } else {
+            let opcode = opcode_by_name(token).ok_or_else(|| ScriptError::UnknownOpcode(token.to_string()))?;
+            builder.push_opcode(opcode)
+        };
+    }
+    Ok(builder.into_bytes())
+}
+
+// Inverse of parse_asm. Pushes are written as 0x hex, which parse_asm pushes with the
+// shortest opcode, so only scripts using minimal pushes round trip byte for byte.
+pub fn to_asm(script: &[u8]) -> Result<String, ScriptError> {
+    let tokens: Vec<String> = instructions(script)?
+        .into_iter()
+        .map(|instruction| match instruction {
+            Instruction::Push(data) => format!("0x{}", hex::encode(data)),
+            Instruction::Op(opcode) => opcode_name(opcode).unwrap_or_else(|| format!("OP_UNKNOWN_{:02x}", opcode)),
+        })
+        .collect();
+    Ok(tokens.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_int() {
+        assert_eq!(ScriptBuilder::new().push_int(0).into_bytes(), vec![OP_0]);
+        assert_eq!(ScriptBuilder::new().push_int(-1).into_bytes(), vec![OP_1NEGATE]);
+        assert_eq!(ScriptBuilder::new().push_int(16).into_bytes(), vec![OP_16]);
+        assert_eq!(ScriptBuilder::new().push_int(17).into_bytes(), vec![1, 17]);
+        assert_eq!(ScriptBuilder::new().push_int(128).into_bytes(), vec![2, 0x80, 0x00]);
+        assert_eq!(ScriptBuilder::new().push_int(-128).into_bytes(), vec![2, 0x80, 0x80]);
+        assert_eq!(ScriptBuilder::new().push_int(144).into_bytes(), vec![2, 0x90, 0x00]);
+    }
+
+    #[test]
+    fn test_script_num_round_trip() {
+        for n in [0, 1, -1, 127, 128, -128, 255, 256, -32768, 144, 1 << 31] {
+            assert_eq!(decode_script_num(&encode_script_num(n), MAX_LOCKTIME_NUM_SIZE), Some(n));
+        }
+        assert_eq!(decode_script_num(&encode_script_num(1 << 31), MAX_SCRIPT_NUM_SIZE), None);
+        assert_eq!(decode_script_num(&[1, 2, 3, 4, 5, 6, 7, 8, 0x89], MAX_LOCKTIME_NUM_SIZE), None);
+    }
+
+    #[test]
+    fn test_push_bytes() {
+        assert_eq!(ScriptBuilder::new().push_bytes(&[0xab; 75]).into_bytes()[0], 75);
+        assert_eq!(ScriptBuilder::new().push_bytes(&[0xab; 76]).into_bytes()[..2], [OP_PUSHDATA1, 76]);
+        assert_eq!(ScriptBuilder::new().push_bytes(&[0xab; 256]).into_bytes()[..3], [OP_PUSHDATA2, 0, 1]);
+    }
+
+    #[test]
+    fn test_templates() {
+        let hash = [0x11; 20];
+        assert_eq!(hex::encode(ScriptBuilder::p2pkh(&hash)), format!("76a914{}88ac", "11".repeat(20)));
+        assert_eq!(hex::encode(ScriptBuilder::p2sh(&hash)), format!("a914{}87", "11".repeat(20)));
+        assert_eq!(hex::encode(ScriptBuilder::p2wpkh(&hash)), format!("0014{}", "11".repeat(20)));
+        assert_eq!(hex::encode(ScriptBuilder::p2tr(&[0x22; 32])), format!("5120{}", "22".repeat(32)));
+        let multisig = ScriptBuilder::multisig(2, &[vec![2; 33], vec![3; 33]]);
+        assert_eq!(multisig[0], OP_1 + 1);
+        assert_eq!(multisig[multisig.len() - 2..], [OP_1 + 1, OP_CHECKMULTISIG]);
+    }
+
+    #[test]
+    fn test_count_sigops() {
+        assert_eq!(count_sigops(&ScriptBuilder::p2pkh(&[0; 20]), false), 1);
+        assert_eq!(count_sigops(&ScriptBuilder::p2wpkh(&[0; 20]), false), 0);
+        let multisig = ScriptBuilder::multisig(2, &[vec![2; 33], vec![3; 33], vec![2; 33]]);
+        assert_eq!(count_sigops(&multisig, false), MAX_PUBKEYS_PER_MULTISIG);
+        assert_eq!(count_sigops(&multisig, true), 3);
+        // counting stops at a truncated push
+        assert_eq!(count_sigops(&[OP_CHECKSIG, 0x05, OP_CHECKSIG], false), 1);
+    }
+
+    #[test]
+    fn test_parse_asm() {
+        let script = parse_asm("OP_DUP HASH160 0x1111111111111111111111111111111111111111 OP_EQUALVERIFY OP_CHECKSIG").unwrap();
+        assert_eq!(script, ScriptBuilder::p2pkh(&[0x11; 20]));
+        assert_eq!(parse_asm("144 CSV OP_DROP").unwrap(), vec![2, 0x90, 0x00, OP_CHECKSEQUENCEVERIFY, OP_DROP]);
+        assert_eq!(parse_asm("OP_2 OP_16").unwrap(), vec![OP_1 + 1, OP_16]);
+        assert_eq!(parse_asm("OP_BOGUS"), Err(ScriptError::UnknownOpcode(String::from("OP_BOGUS"))));
+        assert_eq!(parse_asm("0xzz"), Err(ScriptError::InvalidHex(String::from("0xzz"))));
+    }
+
+    #[test]
+    fn test_asm_round_trip() {
+        let htlc = ScriptBuilder::htlc(&[2; 33], &[3; 33], &[0x44; 32], 144);
+        let asm = to_asm(&htlc).unwrap();
+        assert!(asm.starts_with("OP_IF OP_SHA256 0x4444"));
+        assert_eq!(parse_asm(&asm).unwrap(), htlc);
+        assert_eq!(to_asm(&[0x05, 0x01]), Err(ScriptError::TruncatedPush { position: 0 }));
+        // witness programs start with OP_0 or OP_1
+        for script in [ScriptBuilder::p2wpkh(&[0x11; 20]), ScriptBuilder::p2wsh(&[0x22; 32]), ScriptBuilder::p2tr(&[0x33; 32])] {
+            assert_eq!(parse_asm(&to_asm(&script).unwrap()).unwrap(), script);
+        }
+    }
+}
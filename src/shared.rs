@@ -0,0 +1,126 @@
+#![allow(unused)]
+
+use crate::block::{Block, BlockChain, Transaction};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/*
+ Locking discipline:
+ - Everything that only looks at the chain (RPC queries, the miner building a
+   template, peers serving blocks) takes the read lock. Any number of readers
+   can hold it at once.
+ - Only block connection (add_block and friends) takes the write lock, and it
+   should hold it for as short as possible: build and check the block first,
+   then lock and connect.
+ - Never hold a guard across a blocking call (network IO, waiting on another
+   lock) and never try to take the write lock while holding a read guard on the
+   same thread, RwLock is not reentrant and that deadlocks.
+ - The helpers below return owned copies so callers don't keep guards alive by
+   accident. Use read()/write() directly when you need several calls under one lock.
+*/
+#[derive(Clone)]
+pub struct SharedChain {
+    inner: Arc<RwLock<BlockChain>>,
+}
+
+impl SharedChain {
+    pub fn new(chain: BlockChain) -> Self {
+        SharedChain {
+            inner: Arc::new(RwLock::new(chain)),
+        }
+    }
+
+    // A poisoned lock means a writer panicked part way through connecting a block,
+    // with the UTXO set, indexes and store possibly out of step. Carrying on with
+    // that chain would hide the damage, so the panic spreads to every later caller.
+    pub fn read(&self) -> RwLockReadGuard<'_, BlockChain> {
+        self.inner.read().expect("chain lock poisoned by a panicking writer")
+    }
+
+    pub fn write(&self) -> RwLockWriteGuard<'_, BlockChain> {
+        self.inner.write().expect("chain lock poisoned by a panicking writer")
+    }
+
+    pub fn add_block(&self, block: Block) {
+        self.write().add_block(block);
+    }
+
+    pub fn get_block_count(&self) -> usize {
+        self.read().get_block_count()
+    }
+
+    pub fn get_best_block_hash(&self) -> Option<String> {
+        self.read().get_best_block_hash().map(String::from)
+    }
+
+    pub fn get_block_by_hash(&self, hash: &str) -> Option<Block> {
+        self.read().get_block_by_hash(hash).cloned()
+    }
+
+    pub fn get_block_by_height(&self, height: usize) -> Option<Block> {
+        self.read().get_block_by_height(height).cloned()
+    }
+
+    pub fn get_transaction(&self, txid: &str) -> Option<Transaction> {
+        self.read().get_transaction(txid).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_poisoned_lock_is_not_reused() {
+        let shared = SharedChain::new(BlockChain::new());
+        let writer = shared.clone();
+        let _ = thread::spawn(move || {
+            let _guard = writer.write();
+            panic!("connect failed half way");
+        })
+        .join();
+        assert!(thread::spawn(move || shared.get_block_count()).join().is_err());
+    }
+
+    #[test]
+    fn test_concurrent_readers_and_writer() {
+        let shared = SharedChain::new(BlockChain::new());
+
+        let writer = {
+            let shared = shared.clone();
+            thread::spawn(move || {
+                let mut prev_hash = String::new();
+                for height in 0..50 {
+                    let mut block = Block::new(prev_hash.clone());
+                    block.height = height;
+                    block.hash = block.calculate_hash();
+                    prev_hash = block.hash.clone();
+                    shared.add_block(block);
+                }
+            })
+        };
+
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let shared = shared.clone();
+                thread::spawn(move || {
+                    let mut last = 0;
+                    for _ in 0..100 {
+                        let count = shared.get_block_count();
+                        // the chain only ever grows
+                        assert!(count >= last);
+                        last = count;
+                    }
+                })
+            })
+            .collect();
+
+        writer.join().unwrap();
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        assert_eq!(shared.get_block_count(), 50);
+        let tip = shared.get_best_block_hash().unwrap();
+        assert_eq!(shared.get_block_by_hash(&tip).unwrap().height, 49);
+    }
+}
@@ -0,0 +1,372 @@
+#![allow(unused)]
+
+/* Where the chain keeps its blocks, coins and metadata */
+
+use crate::block::Block;
+use crate::codec::{self, DecodeError};
+use crate::utxo::Coin;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/*
+ BlockChain goes through this for everything that should outlive the process:
+ - blocks by hash (the active chain order is rebuilt from the tip)
+ - the UTXO set, keyed by "txid:vout", and the tip hash it is up to date with
+ - small metadata values (undo data per block)
+ Coins are always kept in memory so lookups can hand out references, writes
+ may be buffered until flush().
+*/
+pub trait ChainStore {
+    fn get_block(&self, hash: &str) -> Option<Block>;
+    fn put_block(&mut self, block: &Block);
+
+    fn get_coin(&self, key: &str) -> Option<&Coin>;
+    // Returns the coin that was replaced, if any
+    fn put_coin(&mut self, key: String, coin: Coin) -> Option<Coin>;
+    fn remove_coin(&mut self, key: &str) -> Option<Coin>;
+    fn coin_count(&self) -> usize;
+    fn coins(&self) -> Box<dyn Iterator<Item = (&String, &Coin)> + '_>;
+
+    // Hash of the active chain's tip. Persisted together with the coins so the two
+    // can never disagree after a crash.
+    fn get_tip(&self) -> Option<String>;
+    fn put_tip(&mut self, hash: &str);
+
+    fn get_meta(&self, key: &str) -> Option<Vec<u8>>;
+    fn put_meta(&mut self, key: &str, value: &[u8]);
+
+    // Make everything written so far durable
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct MemoryStore {
+    blocks: HashMap<String, Block>,
+    coins: HashMap<String, Coin>,
+    tip: Option<String>,
+    meta: HashMap<String, Vec<u8>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+}
+
+impl ChainStore for MemoryStore {
+    fn get_block(&self, hash: &str) -> Option<Block> {
+        self.blocks.get(hash).cloned()
+    }
+
+    fn put_block(&mut self, block: &Block) {
+        self.blocks.insert(block.hash.clone(), block.clone());
+    }
+
+    fn get_coin(&self, key: &str) -> Option<&Coin> {
+        self.coins.get(key)
+    }
+
+    fn put_coin(&mut self, key: String, coin: Coin) -> Option<Coin> {
+        self.coins.insert(key, coin)
+    }
+
+    fn remove_coin(&mut self, key: &str) -> Option<Coin> {
+        self.coins.remove(key)
+    }
+
+    fn coin_count(&self) -> usize {
+        self.coins.len()
+    }
+
+    fn coins(&self) -> Box<dyn Iterator<Item = (&String, &Coin)> + '_> {
+        Box::new(self.coins.iter())
+    }
+
+    fn get_tip(&self) -> Option<String> {
+        self.tip.clone()
+    }
+
+    fn put_tip(&mut self, hash: &str) {
+        self.tip = Some(hash.to_string());
+    }
+
+    fn get_meta(&self, key: &str) -> Option<Vec<u8>> {
+        self.meta.get(key).cloned()
+    }
+
+    fn put_meta(&mut self, key: &str, value: &[u8]) {
+        self.meta.insert(key.to_string(), value.to_vec());
+    }
+}
+
+/*
+ Files under one directory:
+   blocks/<hash>   one serialized block each
+   meta/<key>      raw metadata values
+   coins           the tip hash and the whole UTXO set, rewritten on flush when either changed
+ Block and metadata writes wait in memory until flush().
+*/
+pub struct DiskStore {
+    dir: PathBuf,
+    coins: HashMap<String, Coin>,
+    tip: String,
+    coins_dirty: bool, // coins or tip
+    pending_blocks: HashMap<String, Block>,
+    pending_meta: HashMap<String, Vec<u8>>,
+}
+
+fn invalid_data(error: DecodeError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error.to_string())
+}
+
+// Write to a temporary file first so a crash never leaves a half written file behind
+fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, data)?;
+    fs::rename(tmp, path)
+}
+
+// Hashes are hex but metadata keys are free form, keep them to safe file names
+fn file_name(key: &str) -> String {
+    key.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' }).collect()
+}
+
+impl DiskStore {
+    pub fn open(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(dir.join("blocks"))?;
+        fs::create_dir_all(dir.join("meta"))?;
+        let (tip, coins) = match fs::read(dir.join("coins")) {
+            Ok(data) => {
+                let (tip, coins) = codec::deserialize::<(String, Vec<(String, Coin)>)>(&data).map_err(invalid_data)?;
+                (tip, coins.into_iter().collect())
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound => (String::new(), HashMap::new()),
+            Err(error) => return Err(error),
+        };
+        Ok(DiskStore {
+            dir,
+            coins,
+            tip,
+            coins_dirty: false,
+            pending_blocks: HashMap::new(),
+            pending_meta: HashMap::new(),
+        })
+    }
+
+    fn block_path(&self, hash: &str) -> PathBuf {
+        self.dir.join("blocks").join(file_name(hash))
+    }
+
+    fn meta_path(&self, key: &str) -> PathBuf {
+        self.dir.join("meta").join(file_name(key))
+    }
+}
+
+impl ChainStore for DiskStore {
+    fn get_block(&self, hash: &str) -> Option<Block> {
+        if let Some(block) = self.pending_blocks.get(hash) {
+            return Some(block.clone());
+        }
+        Block::deserialize(&fs::read(self.block_path(hash)).ok()?).ok()
+    }
+
+    fn put_block(&mut self, block: &Block) {
+        self.pending_blocks.insert(block.hash.clone(), block.clone());
+    }
+
+    fn get_coin(&self, key: &str) -> Option<&Coin> {
+        self.coins.get(key)
+    }
+
+    fn put_coin(&mut self, key: String, coin: Coin) -> Option<Coin> {
+        self.coins_dirty = true;
+        self.coins.insert(key, coin)
+    }
+
+    fn remove_coin(&mut self, key: &str) -> Option<Coin> {
+        let coin = self.coins.remove(key)?;
+        self.coins_dirty = true;
+        Some(coin)
+    }
+
+    fn coin_count(&self) -> usize {
+        self.coins.len()
+    }
+
+    fn coins(&self) -> Box<dyn Iterator<Item = (&String, &Coin)> + '_> {
+        Box::new(self.coins.iter())
+    }
+
+    // Empty until a chain has been written
+    fn get_tip(&self) -> Option<String> {
+        Some(self.tip.clone()).filter(|tip| !tip.is_empty())
+    }
+
+    fn put_tip(&mut self, hash: &str) {
+        if self.tip != hash {
+            self.tip = hash.to_string();
+            self.coins_dirty = true;
+        }
+    }
+
+    fn get_meta(&self, key: &str) -> Option<Vec<u8>> {
+        match self.pending_meta.get(key) {
+            Some(value) => Some(value.clone()),
+            None => fs::read(self.meta_path(key)).ok(),
+        }
+    }
+
+    fn put_meta(&mut self, key: &str, value: &[u8]) {
+        self.pending_meta.insert(key.to_string(), value.to_vec());
+    }
+
+    // Blocks and undo metadata first, then the tip and coins in one atomic write. A crash
+    // before that last write leaves the previous tip with its matching coins, plus some
+    // files nothing points at yet.
+    fn flush(&mut self) -> io::Result<()> {
+        for (hash, block) in &self.pending_blocks {
+            write_atomic(&self.block_path(hash), &block.serialize())?;
+        }
+        self.pending_blocks.clear();
+        for (key, value) in &self.pending_meta {
+            write_atomic(&self.meta_path(key), value)?;
+        }
+        self.pending_meta.clear();
+        if self.coins_dirty {
+            let coins: Vec<(&String, &Coin)> = self.coins.iter().collect();
+            write_atomic(&self.dir.join("coins"), &codec::serialize(&(&self.tip, coins)))?;
+            self.coins_dirty = false;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::{BlockChain, Transaction, TxIn, TxOut};
+    use std::collections::LinkedList as List;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("chainstore-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn block() -> Block {
+        let mut block = Block::new(String::new());
+        block.add_transaction(Transaction::new(List::new(), List::from([TxOut::new(String::from("a"), 50)])));
+        block
+    }
+
+    #[test]
+    fn test_memory_store() {
+        let mut store = MemoryStore::new();
+        let block = block();
+        store.put_block(&block);
+        assert_eq!(store.get_block(&block.hash).unwrap().hash, block.hash);
+        assert!(store.put_coin(String::from("a:0"), Coin::new(TxOut::new(String::from("a"), 1), 0)).is_none());
+        assert_eq!(store.coin_count(), 1);
+        assert_eq!(store.remove_coin("a:0").unwrap().txout.satoshis, 1);
+        store.put_meta("undo-x", b"undo");
+        assert_eq!(store.get_meta("undo-x"), Some(b"undo".to_vec()));
+        assert_eq!(store.get_tip(), None);
+        store.put_tip(&block.hash);
+        assert_eq!(store.get_tip(), Some(block.hash));
+    }
+
+    #[test]
+    fn test_disk_store_persists_after_flush() {
+        let dir = temp_dir("persist");
+        let block = block();
+        {
+            let mut store = DiskStore::open(&dir).unwrap();
+            store.put_block(&block);
+            store.put_coin(String::from("a:0"), Coin::new(TxOut::new(String::from("a"), 7), 3));
+            store.put_tip(&block.hash);
+            store.put_meta("undo-x", b"undo");
+            // readable before the flush, but not on disk yet
+            assert!(store.get_block(&block.hash).is_some());
+            assert!(!dir.join("coins").exists());
+            store.flush().unwrap();
+        }
+        let store = DiskStore::open(&dir).unwrap();
+        assert_eq!(store.get_block(&block.hash).unwrap().hash, block.hash);
+        assert_eq!(store.get_coin("a:0").unwrap().height, 3);
+        assert_eq!(store.get_tip(), Some(block.hash));
+        assert_eq!(store.get_meta("undo-x"), Some(b"undo".to_vec()));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_chain_reloads_from_disk() {
+        let dir = temp_dir("reload");
+        let genesis = block();
+        let coinbase = genesis.transactions.front().unwrap().txid.clone();
+        let mut block1 = Block::new(genesis.hash.clone());
+        block1.height = 1;
+        let spend = TxIn::new(coinbase.clone(), 0, String::from("signature"));
+        block1.add_transaction(Transaction::new(List::from([spend]), List::from([TxOut::new(String::from("b"), 40)])));
+
+        let mut chain = BlockChain::with_store(DiskStore::open(&dir).unwrap());
+        chain.add_block(genesis.clone());
+        chain.add_block(block1.clone());
+        chain.flush().unwrap();
+        let utxo_hash = *chain.utxo_set_hash();
+        drop(chain);
+
+        let mut chain = BlockChain::load(DiskStore::open(&dir).unwrap());
+        assert_eq!(chain.get_block_count(), 2);
+        assert_eq!(chain.get_best_block_hash(), Some(block1.hash.as_str()));
+        assert_eq!(chain.get_utxo_count(), 1);
+        assert_eq!(chain.utxo_stats().count, 1);
+        assert_eq!(*chain.utxo_set_hash(), utxo_hash);
+        assert!(chain.get_transaction(&coinbase).is_some());
+        assert_eq!(chain.get_spender(&coinbase, 0).map(|(_, input)| input), Some(0));
+        // undo data came back too, so the reloaded tip can be disconnected
+        chain.disconnect_tip();
+        assert_eq!(chain.get_utxo(&coinbase, 0).unwrap().satoshis, 50);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_crash_before_coins_keeps_old_tip() {
+        let dir = temp_dir("crash");
+        let genesis = block();
+        let coinbase = genesis.transactions.front().unwrap().txid.clone();
+        let mut block1 = Block::new(genesis.hash.clone());
+        block1.height = 1;
+        let spend = TxIn::new(coinbase.clone(), 0, String::from("signature"));
+        block1.add_transaction(Transaction::new(List::from([spend]), List::from([TxOut::new(String::from("b"), 40)])));
+
+        let mut chain = BlockChain::with_store(DiskStore::open(&dir).unwrap());
+        chain.add_block(genesis.clone());
+        chain.flush().unwrap();
+        let coins_at_genesis = fs::read(dir.join("coins")).unwrap();
+        chain.add_block(block1);
+        chain.flush().unwrap();
+        drop(chain);
+        // as if the process died after writing block1 and its undo data, before the coins
+        fs::write(dir.join("coins"), coins_at_genesis).unwrap();
+
+        let chain = BlockChain::load(DiskStore::open(&dir).unwrap());
+        assert_eq!(chain.get_best_block_hash(), Some(genesis.hash.as_str()));
+        assert_eq!(chain.get_utxo(&coinbase, 0).unwrap().satoshis, 50);
+        assert_eq!(chain.get_utxo_count(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_disk_store_rejects_corrupt_coins() {
+        let dir = temp_dir("corrupt");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("coins"), [0xff]).unwrap();
+        assert_eq!(DiskStore::open(&dir).err().map(|e| e.kind()), Some(io::ErrorKind::InvalidData));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
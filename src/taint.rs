@@ -0,0 +1,113 @@
+#![allow(unused)]
+
+/* Coin tracing: follow funds from some source outputs forward through the spend
+   graph. Every transaction passes taint on in proportion ("haircut"): an output
+   gets value * tainted in / total in, so what goes to fees is lost from the trace. */
+
+use crate::block::{outpoint_key, BlockChain};
+use crate::store::ChainStore;
+use std::collections::{HashMap, VecDeque};
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TaintReport {
+    // Tainted satoshis in unspent outputs at the tip, per address
+    pub by_address: HashMap<String, u64>,
+    pub total: u64,
+}
+
+impl<S: ChainStore> BlockChain<S> {
+    // Sources are confirmed outputs as (txid, vout), fully tainted. Unknown ones are skipped.
+    pub fn trace_taint(&self, sources: &[(&str, usize)]) -> TaintReport {
+        let mut taint: HashMap<String, u64> = HashMap::new();
+        let mut queue = VecDeque::new();
+        for (txid, vout) in sources {
+            let Some(txout) = self.get_transaction(txid).and_then(|tx| tx.outputs.iter().nth(*vout)) else { continue };
+            taint.insert(outpoint_key(txid, *vout), txout.satoshis);
+            queue.extend(self.get_spender(txid, *vout).map(|(spender, _)| spender));
+        }
+
+        // A transaction is worked out again whenever one of its inputs changes,
+        // so the order spends are found in doesn't matter
+        while let Some(txid) = queue.pop_front() {
+            let Some(tx) = self.get_transaction(txid) else { continue };
+            let mut total_in: u128 = 0;
+            let mut tainted_in: u128 = 0;
+            for txin in &tx.inputs {
+                let key = outpoint_key(&txin.prev_txid, txin.out as usize);
+                // an input we can't price counts as untainted and worth nothing
+                let value = self
+                    .get_transaction(&txin.prev_txid)
+                    .and_then(|prev| prev.outputs.iter().nth(txin.out as usize))
+                    .map_or(0, |txout| txout.satoshis);
+                total_in += value as u128;
+                tainted_in += taint.get(&key).copied().unwrap_or(0).min(value) as u128;
+            }
+            if tainted_in == 0 {
+                continue;
+            }
+            for (vout, txout) in tx.outputs.iter().enumerate() {
+                let share = (txout.satoshis as u128 * tainted_in / total_in) as u64;
+                let key = outpoint_key(&tx.txid, vout);
+                if taint.get(&key) != Some(&share) {
+                    taint.insert(key, share);
+                    queue.extend(self.get_spender(&tx.txid, vout).map(|(spender, _)| spender));
+                }
+            }
+        }
+
+        let mut report = TaintReport::default();
+        for (key, tainted) in taint {
+            if tainted == 0 {
+                continue;
+            }
+            let Some(coin) = self.store().get_coin(&key) else { continue };
+            *report.by_address.entry(coin.txout.public_address.clone()).or_default() += tainted;
+            report.total += tainted;
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::{Transaction, TxIn};
+    use crate::builder::{BlockBuilder, TransactionBuilder};
+
+    fn input(tx: &Transaction, vout: u32) -> TxIn {
+        TxIn::new(tx.txid.clone(), vout, String::from("signature"))
+    }
+
+    #[test]
+    fn test_trace_taint() {
+        let stolen = TransactionBuilder::new().add_output("thief", 100).build().unwrap();
+        let clean = TransactionBuilder::new().add_output("exchange", 100).build().unwrap();
+        // half the inputs are tainted, so half of each output is
+        let mix = TransactionBuilder::new()
+            .add_input(input(&stolen, 0))
+            .add_input(input(&clean, 0))
+            .add_output("alice", 150)
+            .add_output("bob", 50)
+            .build()
+            .unwrap();
+        // bob passes his 50 on minus a 10 sat fee
+        let pay = TransactionBuilder::new().add_input(input(&mix, 1)).add_output("carol", 40).build().unwrap();
+
+        let genesis = BlockBuilder::new().add_tx(stolen.clone()).add_tx(clean.clone()).build().unwrap();
+        let block1 = BlockBuilder::new().parent(&genesis).add_tx(mix).add_tx(pay).build().unwrap();
+        let mut blockchain = BlockChain::new();
+        blockchain.add_block(genesis);
+        blockchain.add_block(block1);
+        assert_eq!(blockchain.get_block_count(), 2);
+
+        let report = blockchain.trace_taint(&[(&stolen.txid, 0)]);
+        assert_eq!(report.by_address, HashMap::from([(String::from("alice"), 75), (String::from("carol"), 20)]));
+        assert_eq!(report.total, 95);
+
+        assert_eq!(blockchain.trace_taint(&[(&clean.txid, 5)]), TaintReport::default());
+        // the clean coin makes up the other half of the same outputs
+        assert_eq!(blockchain.trace_taint(&[(&clean.txid, 0)]), report);
+        let both = blockchain.trace_taint(&[(&stolen.txid, 0), (&clean.txid, 0)]);
+        assert_eq!(both.by_address["alice"], 150);
+    }
+}
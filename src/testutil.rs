@@ -0,0 +1,277 @@
+#![allow(unused)]
+
+/* Random chain data for property style tests */
+
+use crate::block::{Block, Transaction, TxIn, TxOut, MAX_BLOCK_SIGOPS_COST, MAX_MONEY, SEQUENCE_FINAL, WITNESS_SCALE_FACTOR};
+use crate::builder::BlockBuilder;
+use std::collections::LinkedList as List;
+
+// Outputs a generated chain can still spend, as (txid, vout, satoshis)
+pub type Coins = Vec<(String, u32, u64)>;
+
+// xorshift64*, good enough for test data and fully reproducible from the seed
+#[derive(Clone)]
+pub struct Gen {
+    state: u64,
+}
+
+impl Gen {
+    pub fn new(seed: u64) -> Self {
+        // xorshift gets stuck on 0, so scramble the seed first to keep small seeds
+        // (0 included) apart. Only the constant itself scrambles to 0.
+        let state = seed ^ 0x9E3779B97F4A7C15;
+        Gen { state: if state == 0 { 0x9E3779B97F4A7C15 } else { state } }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545F4914F6CDD1D)
+    }
+
+    // Random number in low..high (high excluded)
+    pub fn range(&mut self, low: u64, high: u64) -> u64 {
+        low + self.next_u64() % (high - low)
+    }
+
+    pub fn bool(&mut self) -> bool {
+        self.next_u64() & 1 == 1
+    }
+
+    pub fn hex_string(&mut self, bytes: usize) -> String {
+        let data: Vec<u8> = (0..bytes).map(|_| self.next_u64() as u8).collect();
+        hex::encode(data)
+    }
+
+    pub fn txin(&mut self) -> TxIn {
+        let sequence = if self.bool() { SEQUENCE_FINAL } else { self.next_u64() as u32 };
+        TxIn::with_sequence(self.hex_string(32), self.range(0, 4) as u32, self.hex_string(16), sequence)
+    }
+
+    pub fn txout(&mut self) -> TxOut {
+        TxOut::new(self.hex_string(20), self.range(1, 50 * 100_000_000))
+    }
+
+    pub fn transaction(&mut self) -> Transaction {
+        let inputs: List<TxIn> = (0..self.range(1, 4)).map(|_| self.txin()).collect();
+        let outputs: List<TxOut> = (0..self.range(1, 4)).map(|_| self.txout()).collect();
+        Transaction::new(inputs, outputs)
+    }
+
+    pub fn coinbase(&mut self) -> Transaction {
+        let outputs: List<TxOut> = (0..self.range(1, 4)).map(|_| self.txout()).collect();
+        Transaction::new(List::new(), outputs)
+    }
+
+    // Spends one to three of `coins` and adds its own outputs to them. Outputs never
+    // add up to more than the inputs, whatever is left over is the fee.
+    pub fn spend(&mut self, coins: &mut Coins) -> Option<Transaction> {
+        if coins.is_empty() {
+            return None;
+        }
+        let mut inputs = List::new();
+        let mut value = 0;
+        for _ in 0..self.range(1, 4).min(coins.len() as u64) {
+            let (txid, vout, satoshis) = coins.swap_remove(self.range(0, coins.len() as u64) as usize);
+            inputs.push_back(TxIn::new(txid, vout, self.hex_string(16)));
+            value += satoshis;
+        }
+        let count = self.range(1, 4);
+        let outputs: List<TxOut> = (0..count).map(|_| TxOut::new(self.hex_string(20), self.range(0, value / count + 1))).collect();
+        let tx = Transaction::new(inputs, outputs);
+        for (vout, txout) in tx.outputs.iter().enumerate() {
+            coins.push((tx.txid.clone(), vout as u32, txout.satoshis));
+        }
+        Some(tx)
+    }
+
+    // A transaction that breaks a rule, with the reason it should be rejected for.
+    // No inputs isn't one of them, that's how coinbase transactions look here.
+    pub fn malformed_transaction(&mut self) -> (Transaction, &'static str) {
+        let mut tx = self.transaction();
+        match self.range(0, 3) {
+            0 => {
+                tx.txid = self.hex_string(32); // doesn't match the contents
+                (tx, "bad-txnmrklroot")
+            }
+            1 => {
+                tx.outputs.push_back(TxOut::new(self.hex_string(20), MAX_MONEY + 1));
+                (Transaction::new(tx.inputs, tx.outputs), "bad-txns-vout-toolarge")
+            }
+            _ => {
+                // each output is in range, their sum isn't
+                let outputs = List::from([TxOut::new(self.hex_string(20), MAX_MONEY), TxOut::new(self.hex_string(20), 1)]);
+                (Transaction::new(tx.inputs, outputs), "bad-txns-txouttotal-toolarge")
+            }
+        }
+    }
+
+    pub fn block(&mut self, prev_hash: String, height: u64) -> Block {
+        let mut builder = BlockBuilder::new().prev_hash(&prev_hash, height).nonce(self.next_u64());
+        for _ in 0..self.range(1, 5) {
+            builder = builder.add_tx(self.transaction());
+        }
+        builder.build().expect("generated blocks are well formed")
+    }
+
+    // A block whose parent is not part of any chain
+    pub fn orphan_block(&mut self, height: u64) -> Block {
+        let prev_hash = self.hex_string(32);
+        self.block(prev_hash, height.max(1))
+    }
+
+    // A coinbase and then transactions spending `coins`, and outputs made earlier in
+    // the same block. The coinbase outputs are only spendable from the next block on.
+    pub fn spending_block(&mut self, prev_hash: String, height: u64, coins: &mut Coins) -> Block {
+        let coinbase = self.coinbase();
+        let mut builder = BlockBuilder::new().prev_hash(&prev_hash, height).nonce(self.next_u64()).add_tx(coinbase.clone());
+        for _ in 0..self.range(1, 5) {
+            let Some(tx) = self.spend(coins) else { break };
+            builder = builder.add_tx(tx);
+        }
+        for (vout, txout) in coinbase.outputs.iter().enumerate() {
+            coins.push((coinbase.txid.clone(), vout as u32, txout.satoshis));
+        }
+        builder.build().expect("generated blocks are well formed")
+    }
+
+    // A block on top of `parent` that breaks a rule, with the reason it should be rejected for
+    pub fn malformed_block(&mut self, parent: &Block) -> (Block, &'static str) {
+        let mut block = self.block(parent.hash.clone(), parent.height + 1);
+        match self.range(0, 4) {
+            0 => {
+                block.nonce += 1; // no longer matches the hash
+                (block, "bad-blk-hash")
+            }
+            1 => {
+                block.height += self.range(1, 5);
+                block.hash = block.calculate_hash();
+                (block, "bad-height")
+            }
+            2 => {
+                block.add_transaction(Transaction::new(List::new(), List::from([TxOut::new(self.hex_string(20), MAX_MONEY + 1)])));
+                (block, "bad-txns-vout-toolarge")
+            }
+            _ => {
+                // one p2pkh output is one sigop
+                let p2pkh = String::from("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2");
+                let count = MAX_BLOCK_SIGOPS_COST / WITNESS_SCALE_FACTOR + 1;
+                let outputs: List<TxOut> = (0..count).map(|_| TxOut::new(p2pkh.clone(), 1)).collect();
+                block.add_transaction(Transaction::new(List::new(), outputs));
+                (block, "bad-blk-sigops")
+            }
+        }
+    }
+
+    // Blocks linked from genesis, ready to be added in order. Every block after
+    // genesis spends outputs of the blocks before it.
+    pub fn chain(&mut self, len: usize) -> Vec<Block> {
+        let mut blocks: Vec<Block> = Vec::with_capacity(len);
+        let mut coins = Coins::new();
+        for height in 0..len as u64 {
+            let prev_hash = blocks.last().map(|b| b.hash.clone()).unwrap_or_default();
+            let block = self.spending_block(prev_hash, height, &mut coins);
+            blocks.push(block);
+        }
+        blocks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::BlockChain;
+
+    #[test]
+    fn test_same_seed_same_data() {
+        let a = Gen::new(7).chain(3);
+        let b = Gen::new(7).chain(3);
+        let hashes = |blocks: &[Block]| blocks.iter().map(|b| b.hash.clone()).collect::<Vec<_>>();
+        assert_eq!(hashes(&a), hashes(&b));
+    }
+
+    #[test]
+    fn test_seeds_give_distinct_streams() {
+        let first = |seed| Gen::new(seed).next_u64();
+        assert_ne!(first(0), first(1));
+    }
+
+    #[test]
+    fn test_generated_chains_connect() {
+        for seed in 0..20 {
+            let mut gen = Gen::new(seed);
+            let len = gen.range(1, 10) as usize;
+            let mut blockchain = BlockChain::new();
+            for block in gen.chain(len) {
+                blockchain.add_block(block);
+            }
+            assert_eq!(blockchain.get_block_count(), len);
+        }
+    }
+
+    #[test]
+    fn test_disconnect_restores_utxo_set() {
+        for seed in 0..20 {
+            let mut gen = Gen::new(seed);
+            let len = gen.range(2, 10) as usize;
+            let blocks = gen.chain(len);
+            let connected = gen.range(1, len as u64) as usize;
+            let mut blockchain = BlockChain::new();
+            for block in &blocks[..connected] {
+                blockchain.add_block(block.clone());
+            }
+            let (hash, count) = (*blockchain.utxo_set_hash(), blockchain.get_utxo_count());
+            for block in &blocks[connected..] {
+                blockchain.add_block(block.clone());
+            }
+            assert_eq!(blockchain.get_block_count(), len);
+            // the later blocks spent coins that were there before them
+            assert!(blocks[connected..].iter().flat_map(|block| &block.transactions).any(|tx| !tx.inputs.is_empty()));
+            for _ in connected..len {
+                blockchain.disconnect_tip();
+            }
+            assert_eq!(*blockchain.utxo_set_hash(), hash);
+            assert_eq!(blockchain.get_utxo_count(), count);
+        }
+    }
+
+    #[test]
+    fn test_orphan_blocks_rejected() {
+        for seed in 0..20 {
+            let mut gen = Gen::new(seed);
+            let mut blockchain = BlockChain::new();
+            blockchain.add_block(gen.block(String::new(), 0));
+            blockchain.add_block(gen.orphan_block(1));
+            assert_eq!(blockchain.get_block_count(), 1);
+        }
+    }
+
+    #[test]
+    fn test_malformed_transactions_rejected() {
+        let blockchain = BlockChain::new();
+        for seed in 0..20 {
+            let (tx, reason) = Gen::new(seed).malformed_transaction();
+            let rejected = BlockBuilder::new().add_tx(tx).build().and_then(|block| blockchain.check_block(&block));
+            assert_eq!(rejected, Err(reason));
+        }
+    }
+
+    #[test]
+    fn test_malformed_blocks_rejected() {
+        for seed in 0..20 {
+            let mut gen = Gen::new(seed);
+            let mut blockchain = BlockChain::new();
+            for block in gen.chain(2) {
+                blockchain.add_block(block);
+            }
+            let tip = blockchain.get_block_by_height(1).unwrap().clone();
+            let (block, reason) = gen.malformed_block(&tip);
+            if reason != "bad-blk-hash" && reason != "bad-height" {
+                assert_eq!(blockchain.check_block(&block), Err(reason));
+            }
+            assert_eq!(blockchain.submit_block(block).reason(), Some(reason));
+            assert_eq!(blockchain.get_block_count(), 2);
+        }
+    }
+}
@@ -0,0 +1,128 @@
+#![allow(unused)]
+
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Largest offset (in seconds) we are willing to apply to the local clock
+pub const MAX_TIME_ADJUSTMENT: i64 = 70 * 60;
+// Only remember this many peer samples, like bitcoin core
+pub const MAX_TIME_SAMPLES: usize = 200;
+// We need at least this many samples before adjusting anything
+pub const MIN_TIME_SAMPLES: usize = 5;
+// A block timestamp may be at most 2 hours ahead of network adjusted time
+pub const MAX_FUTURE_BLOCK_TIME: u64 = 2 * 60 * 60;
+// If no peer is within 5 minutes of our clock while the median is out of bounds, warn
+const CLOCK_WARNING_WINDOW: i64 = 5 * 60;
+
+pub fn local_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[derive(Clone, Default)]
+pub struct TimeData {
+    samples: HashMap<String, i64>, // one offset per peer
+    offset: i64,
+    warning: Option<String>,
+}
+
+impl TimeData {
+    pub fn new() -> Self {
+        TimeData::default()
+    }
+
+    // Record the offset (peer time - local time) reported by a peer.
+    // Each peer only gets to vote once, later samples from the same peer are ignored.
+    pub fn add_sample(&mut self, peer: &str, offset: i64) {
+        if self.samples.len() >= MAX_TIME_SAMPLES || self.samples.contains_key(peer) {
+            return;
+        }
+        self.samples.insert(peer.to_string(), offset);
+
+        // Like core, only recompute on an odd number of samples so the median is a real sample
+        let count = self.samples.len();
+        if count < MIN_TIME_SAMPLES || count.is_multiple_of(2) {
+            return;
+        }
+
+        let mut offsets: Vec<i64> = self.samples.values().copied().collect();
+        offsets.sort_unstable();
+        let median = offsets[count / 2];
+
+        if median.abs() <= MAX_TIME_ADJUSTMENT {
+            self.offset = median;
+            self.warning = None;
+        } else {
+            self.offset = 0;
+            let close_peer = offsets.iter().any(|o| *o != 0 && o.abs() < CLOCK_WARNING_WINDOW);
+            if !close_peer {
+                self.warning = Some(String::from(
+                    "Please check that your computer's date and time are correct! \
+                     If your clock is wrong the node will not work properly.",
+                ));
+            }
+        }
+    }
+
+    pub fn offset(&self) -> i64 {
+        self.offset
+    }
+
+    pub fn sample_count(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn warning(&self) -> Option<&str> {
+        self.warning.as_deref()
+    }
+
+    pub fn adjusted_time(&self) -> u64 {
+        local_time().saturating_add_signed(self.offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_samples(offsets: &[i64]) -> TimeData {
+        let mut data = TimeData::new();
+        for (i, offset) in offsets.iter().enumerate() {
+            data.add_sample(&format!("peer{}", i), *offset);
+        }
+        data
+    }
+
+    #[test]
+    fn test_needs_minimum_samples() {
+        let data = with_samples(&[60, 60, 60, 60]);
+        assert_eq!(data.offset(), 0);
+        let data = with_samples(&[60, 60, 60, 60, 60]);
+        assert_eq!(data.offset(), 60);
+    }
+
+    #[test]
+    fn test_uses_median() {
+        let data = with_samples(&[-10, 5, 1000, 20, 30]);
+        assert_eq!(data.offset(), 20);
+    }
+
+    #[test]
+    fn test_duplicate_peer_ignored() {
+        let mut data = TimeData::new();
+        for _ in 0..5 {
+            data.add_sample("peer", 100);
+        }
+        assert_eq!(data.sample_count(), 1);
+        assert_eq!(data.offset(), 0);
+    }
+
+    #[test]
+    fn test_out_of_bounds_median_warns() {
+        let data = with_samples(&[MAX_TIME_ADJUSTMENT + 1; 5]);
+        assert_eq!(data.offset(), 0);
+        assert!(data.warning().is_some());
+    }
+}
@@ -0,0 +1,236 @@
+#![allow(unused)]
+
+use crate::address::validate_address;
+use crate::block::TxOut;
+use crate::codec::{Decodable, DecodeError, Encodable, Reader};
+use crate::hashes::{sha256, Sha256Hash};
+use std::collections::HashMap;
+
+// Outputs below this are uneconomical to spend (bitcoin core's P2PKH dust limit)
+pub const DUST_THRESHOLD: u64 = 546;
+// Upper bounds (exclusive) of the value buckets in satoshis, the last bucket is everything above
+pub const VALUE_BUCKETS: [u64; 5] = [1_000, 100_000, 1_000_000, 100_000_000, 1_000_000_000];
+// Upper bounds (exclusive) of the age buckets in confirmations
+pub const AGE_BUCKETS: [usize; 4] = [6, 100, 1_000, 10_000];
+
+// An unspent output and the height of the block that created it
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Coin {
+    pub txout: TxOut,
+    pub height: usize,
+}
+
+impl Coin {
+    pub fn new(txout: TxOut, height: usize) -> Self {
+        Coin { txout, height }
+    }
+}
+
+impl Encodable for Coin {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.txout.encode(buf);
+        (self.height as u64).encode(buf);
+    }
+}
+
+impl Decodable for Coin {
+    fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let txout = TxOut::decode(reader)?;
+        Ok(Coin::new(txout, u64::decode(reader)? as usize))
+    }
+}
+
+/*
+ Rolling commitment to the UTXO set. Every (outpoint key, coin) maps to a 256 bit
+ number and the set hashes to their sum mod 2^256, so coins can be added and removed
+ one at a time in any order and equal sets always give equal hashes.
+ Same idea as bitcoin core's MuHash but additive instead of a 3072 bit multiplicative
+ group: much cheaper, though someone picking coins on purpose could find collisions.
+ Good enough for nodes checking they agree, not as a trustless commitment.
+*/
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct UtxoSetHash {
+    sum: [u64; 4], // little endian limbs
+}
+
+impl UtxoSetHash {
+    fn element(key: &str, coin: &Coin) -> [u64; 4] {
+        let mut data = Vec::new();
+        key.encode(&mut data);
+        coin.encode(&mut data);
+        let hash = sha256(&data);
+        let mut limbs = [0u64; 4];
+        for (limb, chunk) in limbs.iter_mut().zip(hash.0.chunks_exact(8)) {
+            *limb = u64::from_le_bytes(chunk.try_into().expect("8 byte chunk"));
+        }
+        limbs
+    }
+
+    pub fn insert(&mut self, key: &str, coin: &Coin) {
+        let mut carry = false;
+        for (limb, add) in self.sum.iter_mut().zip(Self::element(key, coin)) {
+            let (sum, overflow1) = limb.overflowing_add(add);
+            let (sum, overflow2) = sum.overflowing_add(carry as u64);
+            *limb = sum;
+            carry = overflow1 || overflow2;
+        }
+    }
+
+    pub fn remove(&mut self, key: &str, coin: &Coin) {
+        let mut borrow = false;
+        for (limb, sub) in self.sum.iter_mut().zip(Self::element(key, coin)) {
+            let (diff, underflow1) = limb.overflowing_sub(sub);
+            let (diff, underflow2) = diff.overflowing_sub(borrow as u64);
+            *limb = diff;
+            borrow = underflow1 || underflow2;
+        }
+    }
+
+    // What gets compared between nodes
+    pub fn digest(&self) -> Sha256Hash {
+        let bytes: Vec<u8> = self.sum.iter().flat_map(|limb| limb.to_le_bytes()).collect();
+        sha256(&bytes)
+    }
+}
+
+// Output type as reported by validate_address, "unknown" for anything that doesn't decode
+pub fn address_type(address: &str) -> &'static str {
+    validate_address(address)
+        .map(|info| info.address_type.as_str())
+        .unwrap_or("unknown")
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UtxoStats {
+    pub count: usize,
+    pub total_value: u128,
+    pub dust_count: usize,
+    pub value_buckets: [usize; VALUE_BUCKETS.len() + 1],
+    pub age_buckets: [usize; AGE_BUCKETS.len() + 1], // only filled in by BlockChain::utxo_stats
+    pub by_type: HashMap<&'static str, (usize, u128)>, // type -> (count, value)
+    by_height: HashMap<usize, usize>,
+}
+
+impl UtxoStats {
+    fn value_bucket(satoshis: u64) -> usize {
+        VALUE_BUCKETS.iter().position(|limit| satoshis < *limit).unwrap_or(VALUE_BUCKETS.len())
+    }
+
+    pub fn add(&mut self, coin: &Coin) {
+        let value = coin.txout.satoshis;
+        self.count += 1;
+        self.total_value += value as u128;
+        if value < DUST_THRESHOLD {
+            self.dust_count += 1;
+        }
+        self.value_buckets[Self::value_bucket(value)] += 1;
+        let by_type = self.by_type.entry(address_type(&coin.txout.public_address)).or_default();
+        by_type.0 += 1;
+        by_type.1 += value as u128;
+        *self.by_height.entry(coin.height).or_default() += 1;
+    }
+
+    pub fn remove(&mut self, coin: &Coin) {
+        let value = coin.txout.satoshis;
+        self.count -= 1;
+        self.total_value -= value as u128;
+        if value < DUST_THRESHOLD {
+            self.dust_count -= 1;
+        }
+        self.value_buckets[Self::value_bucket(value)] -= 1;
+        let kind = address_type(&coin.txout.public_address);
+        if let Some(by_type) = self.by_type.get_mut(kind) {
+            by_type.0 -= 1;
+            by_type.1 -= value as u128;
+            if by_type.0 == 0 {
+                self.by_type.remove(kind);
+            }
+        }
+        if let Some(count) = self.by_height.get_mut(&coin.height) {
+            *count -= 1;
+            if *count == 0 {
+                self.by_height.remove(&coin.height);
+            }
+        }
+    }
+
+    // Ages depend on the tip so they are bucketed on demand
+    pub fn with_ages(&self, block_count: usize) -> UtxoStats {
+        let mut stats = self.clone();
+        stats.age_buckets = Default::default();
+        for (height, count) in &self.by_height {
+            let confirmations = block_count.saturating_sub(*height);
+            let bucket = AGE_BUCKETS.iter().position(|limit| confirmations < *limit).unwrap_or(AGE_BUCKETS.len());
+            stats.age_buckets[bucket] += count;
+        }
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_address_type() {
+        assert_eq!(address_type("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa"), "p2pkh");
+        assert_eq!(address_type("3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy"), "p2sh");
+        assert_eq!(address_type("bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq"), "p2wpkh");
+        // looks like p2pkh but the checksum is wrong
+        assert_eq!(address_type("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNb"), "unknown");
+        assert_eq!(address_type("bc1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3qccfmv3"), "p2wsh");
+        assert_eq!(address_type("bc1p5d7rjq7g6rdk2yhzks9smlaqtedr4dekq08ge8ztwac72sfr9rusxg3297"), "p2tr");
+        assert_eq!(address_type("public_address"), "unknown");
+    }
+
+    #[test]
+    fn test_add_remove() {
+        let mut stats = UtxoStats::default();
+        let address = String::from("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa");
+        let dust = Coin::new(TxOut::new(address.clone(), 100), 0);
+        let big = Coin::new(TxOut::new(address, 2_000_000_000), 5);
+        stats.add(&dust);
+        stats.add(&big);
+        assert_eq!(stats.count, 2);
+        assert_eq!(stats.dust_count, 1);
+        assert_eq!(stats.value_buckets[0], 1);
+        assert_eq!(stats.value_buckets[VALUE_BUCKETS.len()], 1);
+        assert_eq!(stats.by_type["p2pkh"], (2, 2_000_000_100));
+
+        let aged = stats.with_ages(10);
+        assert_eq!(aged.age_buckets[0], 1); // 5 confirmations
+        assert_eq!(aged.age_buckets[1], 1); // 10 confirmations
+
+        stats.remove(&dust);
+        stats.remove(&big);
+        assert_eq!(stats, UtxoStats::default());
+    }
+
+    #[test]
+    fn test_utxo_set_hash() {
+        let coins: Vec<(String, Coin)> =
+            (0..5).map(|i| (format!("txid:{}", i), Coin::new(TxOut::new(String::from("a"), u64::MAX - i), i as usize))).collect();
+        let mut forward = UtxoSetHash::default();
+        let mut backward = UtxoSetHash::default();
+        for (key, coin) in &coins {
+            forward.insert(key, coin);
+        }
+        for (key, coin) in coins.iter().rev() {
+            backward.insert(key, coin);
+        }
+        assert_eq!(forward.digest(), backward.digest());
+        assert_ne!(forward.digest(), UtxoSetHash::default().digest());
+
+        // same coin under another outpoint is a different set
+        let mut moved = UtxoSetHash::default();
+        moved.insert("other:0", &coins[0].1);
+        let mut original = UtxoSetHash::default();
+        original.insert(&coins[0].0, &coins[0].1);
+        assert_ne!(moved, original);
+
+        for (key, coin) in &coins {
+            forward.remove(key, coin);
+        }
+        assert_eq!(forward, UtxoSetHash::default());
+    }
+}
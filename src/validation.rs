@@ -0,0 +1,292 @@
+#![allow(unused)]
+
+/* Block validation as a list of named rules, run in order until one fails */
+
+use crate::block::{money_range, Block, BlockChain, RelativeLockTime, Transaction, MAX_BLOCK_SIGOPS_COST, WITNESS_SCALE_FACTOR};
+use crate::store::ChainStore;
+use crate::timedata::MAX_FUTURE_BLOCK_TIME;
+use std::sync::Arc;
+
+pub trait Rule<S>: Send + Sync {
+    // Short stable name, used to find the rule in a pipeline
+    fn name(&self) -> &'static str;
+    // Err is a bitcoin core style reject reason
+    fn check(&self, chain: &BlockChain<S>, block: &Block) -> Result<(), &'static str>;
+    // The part that holds on any branch, for blocks that don't build on our tip.
+    // Nothing by default, most rules need the coins of the chain the block is on.
+    fn check_context_free(&self, chain: &BlockChain<S>, block: &Block) -> Result<(), &'static str> {
+        Ok(())
+    }
+}
+
+// A block may be at most 2 hours ahead of network adjusted time
+pub struct TimestampRule;
+
+impl<S: ChainStore> Rule<S> for TimestampRule {
+    fn name(&self) -> &'static str {
+        "timestamp"
+    }
+
+    fn check(&self, chain: &BlockChain<S>, block: &Block) -> Result<(), &'static str> {
+        if block.timestamp > chain.get_adjusted_time() + MAX_FUTURE_BLOCK_TIME {
+            return Err("time-too-new");
+        }
+        Ok(())
+    }
+
+    fn check_context_free(&self, chain: &BlockChain<S>, block: &Block) -> Result<(), &'static str> {
+        self.check(chain, block)
+    }
+}
+
+// Every relative lock in the block must have matured. Block based locks count
+// confirmations of the spent output, time based ones compare block timestamps.
+pub struct SequenceLockRule;
+
+impl<S: ChainStore> Rule<S> for SequenceLockRule {
+    fn name(&self) -> &'static str {
+        "sequence-locks"
+    }
+
+    fn check(&self, chain: &BlockChain<S>, block: &Block) -> Result<(), &'static str> {
+        let height = chain.get_block_count();
+        for tx in &block.transactions {
+            for (txin, lock) in tx.inputs.iter().zip(tx.relative_lock_times()) {
+                let Some(lock) = lock else { continue };
+                // outputs created in this same block have no confirmations yet
+                let (prev_height, prev_time) = match chain.get_tx_location(&txin.prev_txid) {
+                    Some((hash, prev_height, _)) => match chain.get_block_by_hash(hash) {
+                        Some(prev_block) => (prev_height, prev_block.timestamp),
+                        None => continue,
+                    },
+                    None if block.get_transaction(&txin.prev_txid).is_some() => (height, block.timestamp),
+                    None => continue, // unknown output, nothing to measure against
+                };
+                let mature = match lock {
+                    RelativeLockTime::Blocks(blocks) => height - prev_height >= blocks as usize,
+                    RelativeLockTime::Seconds(seconds) => block.timestamp.saturating_sub(prev_time) >= seconds as u64,
+                };
+                if !mature {
+                    return Err("bad-txns-nonfinal");
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// The parent must be in the chain. Genesis block has no parent.
+pub struct PrevBlockRule;
+
+impl<S: ChainStore> Rule<S> for PrevBlockRule {
+    fn name(&self) -> &'static str {
+        "prev-block"
+    }
+
+    fn check(&self, chain: &BlockChain<S>, block: &Block) -> Result<(), &'static str> {
+        if block.height > 0 && chain.get_block_by_hash(&block.prev_hash).is_none() {
+            return Err("bad-prevblk");
+        }
+        Ok(())
+    }
+}
+
+pub struct SigopsRule;
+
+impl<S: ChainStore> Rule<S> for SigopsRule {
+    fn name(&self) -> &'static str {
+        "sigops"
+    }
+
+    fn check(&self, chain: &BlockChain<S>, block: &Block) -> Result<(), &'static str> {
+        let sigops: usize = block.transactions.iter().map(Transaction::sigop_count).sum();
+        if sigops * WITNESS_SCALE_FACTOR > MAX_BLOCK_SIGOPS_COST {
+            return Err("bad-blk-sigops");
+        }
+        Ok(())
+    }
+
+    fn check_context_free(&self, chain: &BlockChain<S>, block: &Block) -> Result<(), &'static str> {
+        self.check(chain, block)
+    }
+}
+
+// Output values of every transaction, and fees wherever all the spent coins are known.
+// Sums use checked arithmetic so a crafted block can't wrap them back into range.
+pub struct AmountsRule;
+
+impl<S: ChainStore> Rule<S> for AmountsRule {
+    fn name(&self) -> &'static str {
+        "amounts"
+    }
+
+    fn check(&self, chain: &BlockChain<S>, block: &Block) -> Result<(), &'static str> {
+        let mut fees: u64 = 0;
+        for tx in &block.transactions {
+            let value_out = tx.check_output_values()?;
+            if tx.inputs.is_empty() {
+                continue;
+            }
+            let mut value_in: Option<u64> = Some(0);
+            for txin in &tx.inputs {
+                let prev = chain
+                    .get_coin(&txin.prev_txid, txin.out as usize)
+                    .map(|coin| coin.txout.satoshis)
+                    .or_else(|| {
+                        let prev_tx = block.get_transaction(&txin.prev_txid)?;
+                        prev_tx.outputs.iter().nth(txin.out as usize).map(|txout| txout.satoshis)
+                    });
+                // unknown coin, the fee can't be worked out
+                let Some(prev) = prev else {
+                    value_in = None;
+                    break;
+                };
+                value_in = value_in
+                    .and_then(|total| total.checked_add(prev))
+                    .filter(|total| money_range(prev) && money_range(*total));
+                if value_in.is_none() {
+                    return Err("bad-txns-inputvalues-outofrange");
+                }
+            }
+            let Some(value_in) = value_in else { continue };
+            let fee = value_in.checked_sub(value_out).ok_or("bad-txns-in-belowout")?;
+            fees = fees
+                .checked_add(fee)
+                .filter(|total| money_range(*total))
+                .ok_or("bad-txns-accumulated-fee-outofrange")?;
+        }
+        Ok(())
+    }
+
+    // Fees need the spent coins, output values don't
+    fn check_context_free(&self, chain: &BlockChain<S>, block: &Block) -> Result<(), &'static str> {
+        for tx in &block.transactions {
+            tx.check_output_values()?;
+        }
+        Ok(())
+    }
+}
+
+// Rules are shared (Arc) so cloning a chain doesn't need to clone them
+pub struct RulePipeline<S> {
+    rules: Vec<Arc<dyn Rule<S>>>,
+}
+
+impl<S> Clone for RulePipeline<S> {
+    fn clone(&self) -> Self {
+        RulePipeline { rules: self.rules.clone() }
+    }
+}
+
+impl<S: ChainStore> Default for RulePipeline<S> {
+    // The consensus rules, cheapest first: sigops only looks at the block, timestamp and
+    // prev-block need one lookup each, sequence locks and amounts look up every input.
+    // The first failing rule gives the reject reason, so this order is also the precedence.
+    fn default() -> Self {
+        RulePipeline {
+            rules: vec![
+                Arc::new(SigopsRule),
+                Arc::new(TimestampRule),
+                Arc::new(PrevBlockRule),
+                Arc::new(SequenceLockRule),
+                Arc::new(AmountsRule),
+            ],
+        }
+    }
+}
+
+impl<S> RulePipeline<S> {
+    // No rules at all, every block passes
+    pub fn empty() -> Self {
+        RulePipeline { rules: Vec::new() }
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.rules.iter().map(|rule| rule.name()).collect()
+    }
+
+    pub fn push(&mut self, rule: impl Rule<S> + 'static) {
+        self.rules.push(Arc::new(rule));
+    }
+
+    // Returns false if no rule has that name
+    pub fn disable(&mut self, name: &str) -> bool {
+        let before = self.rules.len();
+        self.rules.retain(|rule| rule.name() != name);
+        self.rules.len() != before
+    }
+
+    // Swap a rule for another in the same position, false if no rule has that name
+    pub fn replace(&mut self, name: &str, rule: impl Rule<S> + 'static) -> bool {
+        match self.rules.iter().position(|existing| existing.name() == name) {
+            Some(index) => {
+                self.rules[index] = Arc::new(rule);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn check(&self, chain: &BlockChain<S>, block: &Block) -> Result<(), &'static str> {
+        self.rules.iter().try_for_each(|rule| rule.check(chain, block))
+    }
+
+    pub fn check_context_free(&self, chain: &BlockChain<S>, block: &Block) -> Result<(), &'static str> {
+        self.rules.iter().try_for_each(|rule| rule.check_context_free(chain, block))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::{TxOut, MAX_MONEY};
+    use std::collections::LinkedList as List;
+
+    fn orphan() -> Block {
+        let mut block = Block::new(String::from("unknown parent"));
+        block.height = 5;
+        block.add_transaction(Transaction::new(List::new(), List::from([TxOut::new(String::from("a"), 50)])));
+        block
+    }
+
+    #[test]
+    fn test_default_rules() {
+        let pipeline = RulePipeline::<crate::store::MemoryStore>::default();
+        assert_eq!(pipeline.names(), vec!["sigops", "timestamp", "prev-block", "sequence-locks", "amounts"]);
+    }
+
+    #[test]
+    fn test_disable_rule() {
+        let mut chain = BlockChain::new();
+        assert_eq!(chain.check_block(&orphan()), Err("bad-prevblk"));
+        assert!(chain.rules_mut().disable("prev-block"));
+        assert!(!chain.rules_mut().disable("prev-block"));
+        assert_eq!(chain.check_block(&orphan()), Ok(()));
+        chain.add_block(orphan());
+        assert_eq!(chain.get_block_count(), 1);
+    }
+
+    #[test]
+    fn test_replace_rule() {
+        // a made up rule: no output may carry more than 1 BTC
+        struct SmallOutputs;
+        impl<S: ChainStore> Rule<S> for SmallOutputs {
+            fn name(&self) -> &'static str {
+                "small-outputs"
+            }
+
+            fn check(&self, _chain: &BlockChain<S>, block: &Block) -> Result<(), &'static str> {
+                let large = block.transactions.iter().flat_map(|tx| tx.outputs.iter()).any(|txout| txout.satoshis > 100_000_000);
+                if large { Err("output-too-large") } else { Ok(()) }
+            }
+        }
+
+        let mut chain = BlockChain::new();
+        let mut genesis = Block::new(String::new());
+        genesis.add_transaction(Transaction::new(List::new(), List::from([TxOut::new(String::from("a"), MAX_MONEY)])));
+        assert_eq!(chain.check_block(&genesis), Ok(()));
+        assert!(chain.rules_mut().replace("amounts", SmallOutputs));
+        assert_eq!(chain.check_block(&genesis), Err("output-too-large"));
+        assert_eq!(chain.rules_mut().names().last(), Some(&"small-outputs"));
+    }
+}